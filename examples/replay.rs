@@ -0,0 +1,54 @@
+//! Replay a `--record` file through the decoder, without hardware:
+//!
+//!     cargo run --example replay -- events.bin
+//!
+//! Recordings hold decoded events, so each one is turned back into the HID
+//! report that would have produced it, fed to `SystemState::update`, and
+//! both are printed.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use shuttle_pro_rs::record;
+use shuttle_pro_rs::{ContourEvents, ContourHidEvent, SystemState};
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args_os().nth(1).map(PathBuf::from) else {
+        eprintln!("usage: replay <recording>");
+        return ExitCode::FAILURE;
+    };
+    let events = match record::load(&path) {
+        Ok(events) => events,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut state = SystemState::new();
+    let mut report = ContourHidEvent {
+        id: 0,
+        jog: 0,
+        wheel: 0,
+        _fill: 0,
+        keys: 0,
+    };
+    // The first report only primes the decoder
+    state.update(report);
+
+    for (ms, evt) in events {
+        match evt {
+            ContourEvents::Jog(x) => report.jog = x,
+            ContourEvents::WheelLeft => report.wheel = report.wheel.wrapping_sub(1),
+            ContourEvents::WheelRight => report.wheel = report.wheel.wrapping_add(1),
+            ContourEvents::ButtonDown(b) => report.keys |= 1 << b,
+            ContourEvents::ButtonUp(b) => report.keys &= !(1 << b),
+        }
+        let decoded = state.update(report);
+        println!("{:>8} ms  {:?} -> {:?}", ms, report, decoded);
+        if decoded != [evt] {
+            println!("          recorded {:?}", evt);
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,385 @@
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+use windows::core::{w, Error, Result, HSTRING, PCSTR, PSTR};
+use windows::Win32::Foundation::{
+    CloseHandle, ERROR_MOD_NOT_FOUND, ERROR_SHARING_VIOLATION, FALSE, WIN32_ERROR,
+};
+use windows::Win32::System::Threading::{
+    CreateProcessA, CREATE_NO_WINDOW, PROCESS_CREATION_FLAGS, PROCESS_INFORMATION,
+    STARTF_USESHOWWINDOW, STARTUPINFOA,
+};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+    KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, MOUSEEVENTF_WHEEL, MOUSEINPUT, VIRTUAL_KEY, VK_CONTROL,
+    VK_LWIN, VK_MENU, VK_SHIFT,
+};
+use windows::Win32::UI::Shell::{
+    ShellExecuteW, SE_ERR_ASSOCINCOMPLETE, SE_ERR_DDEBUSY, SE_ERR_DDEFAIL, SE_ERR_DDETIMEOUT,
+    SE_ERR_DLLNOTFOUND, SE_ERR_NOASSOC, SE_ERR_OOM, SE_ERR_SHARE,
+};
+use windows::Win32::UI::WindowsAndMessaging::{SW_HIDE, SW_SHOWNORMAL};
+
+use crate::i18n::Msg;
+use crate::keys::Key;
+
+/// What a button does, tagged by `type` in the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Post a key press to the target window.
+    Key { key: Key },
+    /// Post several key presses to the target window, in order.
+    Sequence { keys: Vec<Key> },
+    /// Set horizontal scroll speed to `1 << level` steps per wheel tick.
+    ScrollZoom { level: u8 },
+    /// Go back to the scroll speed before the last `ScrollZoom` change.
+    ScrollZoomBack,
+    /// Run a program in the background, without a window.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Open a URL in the default browser.
+    OpenUrl { url: String },
+    /// Switch to the named profile.
+    Profile { name: String },
+    /// Type one character, whatever the keyboard layout, into the focused window.
+    Char {
+        #[serde(rename = "char")]
+        ch: char,
+    },
+    /// Post `WM_APPCOMMAND` with an `APPCOMMAND_*` code to the target window.
+    AppCommand { command: AppCommand },
+    /// Post an arbitrary window message to the target window.
+    RawMessage {
+        message: u32,
+        #[serde(default)]
+        wparam: usize,
+        #[serde(default)]
+        lparam: isize,
+    },
+    /// Send an HID output report, report ID first, to the first HID device
+    /// with that `vid`/`pid`.
+    OutputReport { vid: u16, pid: u16, report: Vec<u8> },
+    /// Add `modifier` to the next key action; with `lock`, to every key
+    /// action until pressed again.
+    StickyModifier {
+        modifier: Modifier,
+        #[serde(default)]
+        lock: bool,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Win,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 4] = [
+        Modifier::Ctrl,
+        Modifier::Shift,
+        Modifier::Alt,
+        Modifier::Win,
+    ];
+
+    pub fn key(self) -> VIRTUAL_KEY {
+        match self {
+            Modifier::Ctrl => VK_CONTROL,
+            Modifier::Shift => VK_SHIFT,
+            Modifier::Alt => VK_MENU,
+            Modifier::Win => VK_LWIN,
+        }
+    }
+
+    /// Bit of this modifier in a set of them.
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// `APPCOMMAND_*` code, written in the config as a number or one of
+/// `AppCommand::NAMED`, e.g. `"volume_mute"`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "CommandCode", into = "CommandCode")]
+pub struct AppCommand(pub u16);
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum CommandCode {
+    Code(u16),
+    Name(String),
+}
+
+impl AppCommand {
+    /// The commands media players commonly honour, by their winuser.h names.
+    pub const NAMED: &'static [(&'static str, u16)] = &[
+        ("volume_mute", 8),
+        ("volume_down", 9),
+        ("volume_up", 10),
+        ("media_next_track", 11),
+        ("media_previous_track", 12),
+        ("media_stop", 13),
+        ("media_play_pause", 14),
+        ("media_play", 46),
+        ("media_pause", 47),
+        ("media_fast_forward", 49),
+        ("media_rewind", 50),
+        ("media_channel_up", 51),
+        ("media_channel_down", 52),
+    ];
+}
+
+impl TryFrom<CommandCode> for AppCommand {
+    type Error = String;
+
+    fn try_from(code: CommandCode) -> std::result::Result<Self, Self::Error> {
+        match code {
+            CommandCode::Code(code) => Ok(AppCommand(code)),
+            CommandCode::Name(name) => AppCommand::NAMED
+                .iter()
+                .find(|(n, _)| name.eq_ignore_ascii_case(n))
+                .map(|(_, code)| AppCommand(*code))
+                .ok_or_else(|| format!("unknown app command {:?}", name)),
+        }
+    }
+}
+
+impl From<AppCommand> for CommandCode {
+    fn from(command: AppCommand) -> CommandCode {
+        match AppCommand::NAMED
+            .iter()
+            .find(|(_, code)| *code == command.0)
+        {
+            Some((name, _)) => CommandCode::Name(name.to_string()),
+            None => CommandCode::Code(command.0),
+        }
+    }
+}
+
+/// Start `program` and return immediately; the child is never waited for.
+pub fn spawn(program: &str, args: &[String], hidden: bool) -> Result<()> {
+    let mut cmdline = quote(program);
+    for arg in args {
+        cmdline.push(' ');
+        cmdline.push_str(&quote(arg));
+    }
+    let mut cmdline = cmdline.into_bytes();
+    cmdline.push(0);
+
+    let (flags, show) = if hidden {
+        (CREATE_NO_WINDOW, SW_HIDE)
+    } else {
+        (PROCESS_CREATION_FLAGS(0), SW_SHOWNORMAL)
+    };
+
+    let si = STARTUPINFOA {
+        cb: mem::size_of::<STARTUPINFOA>() as u32,
+        dwFlags: STARTF_USESHOWWINDOW,
+        wShowWindow: show.0 as u16,
+        ..Default::default()
+    };
+    let mut pi = PROCESS_INFORMATION::default();
+
+    let ok = unsafe {
+        CreateProcessA(
+            PCSTR::null(),
+            PSTR(cmdline.as_mut_ptr()),
+            None,
+            None,
+            FALSE,
+            flags,
+            None,
+            PCSTR::null(),
+            &si,
+            &mut pi,
+        )
+    };
+    ok.ok()?;
+
+    println!("Spawned {} (pid {})", program, pi.dwProcessId);
+    unsafe {
+        CloseHandle(pi.hThread);
+        CloseHandle(pi.hProcess);
+    }
+    Ok(())
+}
+
+/// Type `ch` with `SendInput`, as a down and up per UTF-16 unit, so
+/// characters outside the BMP go out as their surrogate pair.
+pub fn send_char(ch: char) -> Result<()> {
+    let key = |unit: u16, flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(0),
+                wScan: unit,
+                dwFlags: KEYEVENTF_UNICODE | flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    };
+    let inputs: Vec<INPUT> = char_strokes(ch)
+        .into_iter()
+        .map(|(unit, flags)| key(unit, flags))
+        .collect();
+
+    let sent = unsafe { SendInput(&inputs, mem::size_of::<INPUT>() as i32) };
+    // Fewer means another thread's input blocked ours (UIPI)
+    if sent as usize != inputs.len() {
+        return Err(Error::from_win32());
+    }
+    println!("Typed {:?}", ch);
+    Ok(())
+}
+
+/// The strokes typing `ch`: a down and an up for each UTF-16 unit.
+fn char_strokes(ch: char) -> Vec<(u16, KEYBD_EVENT_FLAGS)> {
+    let mut units = [0u16; 2];
+    ch.encode_utf16(&mut units)
+        .iter()
+        .flat_map(|&unit| [(unit, KEYBD_EVENT_FLAGS(0)), (unit, KEYEVENTF_KEYUP)])
+        .collect()
+}
+
+/// One detent of a real mouse wheel.
+const WHEEL_DELTA: i32 = 120;
+
+/// Ctrl + `steps` wheel detents up (`delta` 1) or down (-1), as real input
+/// to the window under the mouse. One `SendInput` call keeps Ctrl held
+/// across the wheel events, and nothing else can come in between.
+pub fn send_ctrl_wheel(delta: i32, steps: u16) -> Result<()> {
+    let ctrl = |flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VK_CONTROL,
+                dwFlags: flags,
+                ..Default::default()
+            },
+        },
+    };
+    let wheel = INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                mouseData: (delta * WHEEL_DELTA) as _,
+                dwFlags: MOUSEEVENTF_WHEEL,
+                ..Default::default()
+            },
+        },
+    };
+    let mut inputs = vec![ctrl(KEYBD_EVENT_FLAGS(0))];
+    inputs.extend(std::iter::repeat_n(wheel, steps as usize));
+    inputs.push(ctrl(KEYEVENTF_KEYUP));
+
+    let sent = unsafe { SendInput(&inputs, mem::size_of::<INPUT>() as i32) };
+    if sent as usize != inputs.len() {
+        return Err(Error::from_win32());
+    }
+    Ok(())
+}
+
+/// Hand `url` to the shell's `open` verb.
+pub fn open_url(url: &str) -> Result<()> {
+    if !looks_like_url(url) {
+        return Err(crate::app_error(&crate::tr(Msg::NotAUrl, &[&url])));
+    }
+    let rc = unsafe {
+        ShellExecuteW(
+            None,
+            w!("open"),
+            &HSTRING::from(url),
+            None,
+            None,
+            SW_SHOWNORMAL,
+        )
+    };
+    // Anything up to 32 is an error code rather than an instance handle
+    if rc.0 <= 32 {
+        return Err(shell_error(rc.0 as u32));
+    }
+    println!("Opened {}", url);
+    Ok(())
+}
+
+/// The error `ShellExecuteW` returned `code` for; it doesn't set the last error.
+fn shell_error(code: u32) -> Error {
+    match code {
+        0 => WIN32_ERROR(SE_ERR_OOM).into(),
+        SE_ERR_NOASSOC | SE_ERR_ASSOCINCOMPLETE => {
+            crate::app_error(&crate::tr(Msg::NoAssociation, &[]))
+        }
+        SE_ERR_DDETIMEOUT | SE_ERR_DDEFAIL | SE_ERR_DDEBUSY => {
+            crate::app_error(&crate::tr(Msg::DdeFailed, &[]))
+        }
+        SE_ERR_SHARE => ERROR_SHARING_VIOLATION.into(),
+        SE_ERR_DLLNOTFOUND => ERROR_MOD_NOT_FOUND.into(),
+        // The rest match the Win32 code of the same number, e.g. SE_ERR_FNF
+        code => WIN32_ERROR(code).into(),
+    }
+}
+
+/// `scheme:rest` with an RFC 3986 scheme; single letters are drive paths, not schemes.
+pub fn looks_like_url(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, rest)) => {
+            scheme.len() > 1
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                && !rest.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// Quote a single command-line argument the way `CommandLineToArgvW` splits it.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut out = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                out.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                out.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                out.extend(std::iter::repeat_n('\\', backslashes));
+                out.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    out.extend(std::iter::repeat_n('\\', backslashes * 2));
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn char_strokes_per_utf16_unit() {
+        let (down, up) = (KEYBD_EVENT_FLAGS(0), KEYEVENTF_KEYUP);
+        assert_eq!(char_strokes('é'), [(0x00E9, down), (0x00E9, up)]);
+        assert_eq!(
+            char_strokes('😀'),
+            [(0xD83D, down), (0xD83D, up), (0xDE00, down), (0xDE00, up)]
+        );
+    }
+}
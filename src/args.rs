@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use shuttle_pro_rs::record::Format;
+use windows::core::Result;
+
+use crate::i18n::Msg;
+use crate::{app_error, tr};
+
+/// Command line switches.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Args {
+    /// `--init`: write the default config and exit.
+    pub init: bool,
+    /// `--force`: let `--init` overwrite an existing config.
+    pub force: bool,
+    /// `--dump-effective-config [FILE]`: show the loaded config, after
+    /// migration and defaults, in a message box or write it to FILE.
+    pub dump: bool,
+    pub dump_path: Option<PathBuf>,
+    /// `--config FILE`, repeatable: load these instead of the located
+    /// config, each one overriding the ones before it.
+    pub config: Vec<PathBuf>,
+    /// `--record FILE`: append every decoded event to FILE.
+    pub record: Option<PathBuf>,
+    /// `--format binary|csv`: format of `--record`, by default from the extension.
+    pub format: Option<Format>,
+    /// `--console`: show the log in a console window.
+    pub console: bool,
+    /// `--pick-window`: send everything to the next window clicked.
+    pub pick_window: bool,
+    /// `--learn`: show which button, jog position or wheel turn each input
+    /// is instead of acting on it.
+    pub learn: bool,
+    /// `--dry-run`: log every action instead of sending anything.
+    pub dry_run: bool,
+    /// `--selftest`: feed made-up reports through the pipeline and show
+    /// what reached the target window.
+    pub selftest: bool,
+    /// `--diagnose [SECONDS]`: capture for SECONDS (30 by default), write
+    /// a report for bug reports and exit.
+    pub diagnose: Option<u32>,
+}
+
+/// Capture length of `--diagnose` without a number.
+const DIAGNOSE_SECS: u32 = 30;
+
+pub fn parse() -> Result<Args> {
+    let mut args = Args::default();
+    let mut argv = std::env::args().skip(1).peekable();
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--init" => args.init = true,
+            "--force" => args.force = true,
+            "--selftest" => args.selftest = true,
+            "--pick-window" => args.pick_window = true,
+            "--console" => args.console = true,
+            "--learn" => args.learn = true,
+            "--dry-run" => args.dry_run = true,
+            "--dump-effective-config" => {
+                args.dump = true;
+                args.dump_path = argv.next_if(|a| !a.starts_with("--")).map(PathBuf::from);
+            }
+            "--diagnose" => {
+                let secs = argv.next_if(|a| !a.starts_with("--"));
+                args.diagnose = Some(match secs {
+                    Some(secs) => secs
+                        .parse()
+                        .map_err(|_| app_error(&tr(Msg::BadArgument, &[&secs])))?,
+                    None => DIAGNOSE_SECS,
+                });
+            }
+            "--config" => match argv.next() {
+                Some(path) => args.config.push(PathBuf::from(path)),
+                None => return Err(app_error(&tr(Msg::BadArgument, &[&arg]))),
+            },
+            "--record" => match argv.next() {
+                Some(path) => args.record = Some(PathBuf::from(path)),
+                None => return Err(app_error(&tr(Msg::BadArgument, &[&arg]))),
+            },
+            "--format" => {
+                let name = argv.next().unwrap_or_default();
+                args.format = Some(
+                    Format::parse(&name)
+                        .ok_or_else(|| app_error(&tr(Msg::BadArgument, &[&name])))?,
+                );
+            }
+            _ => return Err(app_error(&tr(Msg::BadArgument, &[&arg]))),
+        }
+    }
+    Ok(args)
+}
@@ -0,0 +1,103 @@
+use windows::core::{Error, Result, HSTRING};
+use windows::Win32::Foundation::{BOOL, COLORREF, RECT};
+use windows::Win32::Graphics::Gdi::{
+    CreateBitmap, CreateCompatibleBitmap, CreateCompatibleDC, CreateSolidBrush, CreatedHDC,
+    DeleteDC, DeleteObject, DrawTextW, FillRect, GetDC, GetStockObject, ReleaseDC, SelectObject,
+    SetBkMode, SetTextColor, BLACK_BRUSH, DEFAULT_GUI_FONT, DT_CENTER, DT_SINGLELINE, DT_VCENTER,
+    HBRUSH, TRANSPARENT,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateIconIndirect, DrawIconEx, GetSystemMetrics, DI_FLAGS, DI_IMAGE, DI_MASK, HICON, ICONINFO,
+    SM_CXSMICON, SM_CYSMICON,
+};
+
+const BADGE_COLOR: COLORREF = COLORREF(0x0000_00C0); // dark red, 0x00BBGGRR
+const TEXT_COLOR: COLORREF = COLORREF(0x00FF_FFFF);
+
+/// `base` at tray size with `label` in a badge over its lower right corner.
+/// The caller owns the returned icon and must `DestroyIcon` it.
+pub fn render(base: HICON, label: &str) -> Result<HICON> {
+    let cx = unsafe { GetSystemMetrics(SM_CXSMICON) };
+    let cy = unsafe { GetSystemMetrics(SM_CYSMICON) };
+    let badge = RECT {
+        left: cx * 2 / 5,
+        top: cy * 2 / 5,
+        right: cx,
+        bottom: cy,
+    };
+
+    let screen = unsafe { GetDC(None) };
+    let color = unsafe { CreateCompatibleBitmap(screen, cx, cy) };
+    let mask = unsafe { CreateBitmap(cx, cy, 1, 1, None) };
+    let color_dc = unsafe { CreateCompatibleDC(screen) };
+    let mask_dc = unsafe { CreateCompatibleDC(screen) };
+    unsafe { ReleaseDC(None, screen) };
+
+    let drawn = !color.is_invalid()
+        && !mask.is_invalid()
+        && !color_dc.is_invalid()
+        && !mask_dc.is_invalid()
+        && unsafe {
+            let black = HBRUSH(GetStockObject(BLACK_BRUSH).0);
+            let old_color = SelectObject(color_dc, color);
+            let old_mask = SelectObject(mask_dc, mask);
+
+            // Transparent pixels must be black in the image and set in the mask
+            let all = RECT {
+                left: 0,
+                top: 0,
+                right: cx,
+                bottom: cy,
+            };
+            FillRect(color_dc, &all, black);
+            let ok = draw(color_dc, base, cx, cy, DI_IMAGE) && draw(mask_dc, base, cx, cy, DI_MASK);
+
+            // The badge is opaque
+            FillRect(mask_dc, &badge, black);
+            let brush = CreateSolidBrush(BADGE_COLOR);
+            FillRect(color_dc, &badge, brush);
+            DeleteObject(brush);
+
+            let mut text = HSTRING::from(label).as_wide().to_vec();
+            let mut rect = badge;
+            SelectObject(color_dc, GetStockObject(DEFAULT_GUI_FONT));
+            SetBkMode(color_dc, TRANSPARENT);
+            SetTextColor(color_dc, TEXT_COLOR);
+            DrawTextW(
+                color_dc,
+                &mut text,
+                &mut rect,
+                DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+            );
+
+            SelectObject(color_dc, old_color);
+            SelectObject(mask_dc, old_mask);
+            ok
+        };
+
+    let icon = if drawn {
+        let info = ICONINFO {
+            fIcon: BOOL(1),
+            xHotspot: 0,
+            yHotspot: 0,
+            hbmMask: mask,
+            hbmColor: color,
+        };
+        unsafe { CreateIconIndirect(&info) }
+    } else {
+        Err(Error::from_win32())
+    };
+
+    // CreateIconIndirect copies the bitmaps
+    unsafe {
+        DeleteDC(color_dc);
+        DeleteDC(mask_dc);
+        DeleteObject(color);
+        DeleteObject(mask);
+    }
+    icon
+}
+
+fn draw(dc: CreatedHDC, icon: HICON, cx: i32, cy: i32, flags: DI_FLAGS) -> bool {
+    unsafe { DrawIconEx(dc, 0, 0, icon, cx, cy, 0, None, flags) }.as_bool()
+}
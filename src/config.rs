@@ -0,0 +1,1844 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use shuttle_pro_rs::{Model, JOG_MAX};
+use windows::core::Result;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    VIRTUAL_KEY, VK_LEFT, VK_OEM_4, VK_OEM_6, VK_OEM_PLUS, VK_RIGHT, VK_SPACE,
+};
+use winrt_notification::{Duration, LoopableSound, Sound};
+
+use crate::action::{self, Action, Modifier};
+use crate::i18n::{Language, Msg};
+use crate::keys::Key;
+use crate::midi::RelativeMode;
+use crate::vjoy::Axis;
+use crate::{app_error, tr};
+
+/// Schema version written by this build; older files are upgraded by `migrate`.
+pub const CURRENT_VERSION: i64 = 2;
+
+/// Number of buttons reported in the `keys` bitmask, all of which the
+/// ShuttlePRO v2 has; the v1 has fewer, see `check_buttons`. Neither can
+/// press the wheel, so there is no wheel button, and bit 15 is unused.
+pub const BUTTON_COUNT: u16 = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Schema version, see `migrate`.
+    pub version: i64,
+    /// Language of toasts.
+    pub language: Language,
+    /// Name of the profile active at startup, none means top-level settings only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// A repeating button held longer than this is taken as a lost
+    /// release and stopped; 0 never stops it.
+    pub max_hold_ms: u32,
+    /// Pause after this long without ShuttlePRO input, 0 never.
+    pub idle_pause_ms: u32,
+    /// Whether the input that ends a pause is dropped rather than acted on.
+    pub idle_drop_wake: bool,
+    /// How long a chord button waits for its partner.
+    pub chord_window_ms: u32,
+    /// How reports are read from the device.
+    pub input_mode: InputMode,
+    /// Wait this long after launch before looking for the target and the device.
+    pub startup_delay_ms: u32,
+    /// Also write startup, device, config and error events to the Windows Event Log.
+    pub event_log: bool,
+    /// Switch to the profile whose `target` matches the foreground window.
+    pub auto_profile: bool,
+    /// Which profile `auto_profile` picks when several match.
+    pub profile_tiebreak: Tiebreak,
+    /// Device-wide cap on events a second reaching the bindings, 0 none.
+    pub rate_cap: u32,
+    /// What pressing a button without a binding does.
+    pub unmapped: Unmapped,
+    /// What left-clicking the tray icon does.
+    pub tray_click: TrayClick,
+    /// Global hotkey reloading the config files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reload_hotkey: Option<Hotkey>,
+    pub device: Device,
+    pub target: Target,
+    pub wheel: Wheel,
+    pub jog: Jog,
+    /// Button index (as a string, TOML keys can't be numbers) to binding.
+    pub buttons: BTreeMap<String, Binding>,
+    /// Bindings for two buttons pressed together.
+    pub chords: Vec<Chord>,
+    /// Shift buttons and the bindings they switch to while held.
+    pub layers: Vec<Layer>,
+    pub notify: Notify,
+    pub toasts: Toasts,
+    pub midi: Midi,
+    pub vjoy: Vjoy,
+    pub script: Script,
+    pub udp: Udp,
+    pub metrics: Metrics,
+    pub status: StatusServer,
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMode {
+    /// `WM_INPUT` for the `[device]` collection.
+    #[default]
+    RawInput,
+    /// `ReadFile` on the HID device, from a background thread.
+    ReadFile,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tiebreak {
+    /// The profile whose matcher sets the most criteria, then config order.
+    #[default]
+    Specific,
+    /// The first matching profile in the config.
+    Order,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decode {
+    /// The ShuttlePRO's known byte layout.
+    #[default]
+    Fixed,
+    /// Located through the HID report descriptor, falling back to `Fixed`.
+    Descriptor,
+}
+
+/// HID top-level collection raw input is registered for.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Device {
+    pub usage_page: u16,
+    pub usage: u16,
+    /// Drop reports without the expected report id and zero padding byte.
+    pub check_report: bool,
+    /// Where in a report the jog, wheel and keys are.
+    pub decode: Decode,
+    /// Expected report id by `Model::id`, 0 for models not listed.
+    pub report_ids: BTreeMap<String, u8>,
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        // Consumer control, which the ShuttlePRO reports as
+        Device {
+            usage_page: 0x000C,
+            usage: 0x0001,
+            check_report: false,
+            decode: Decode::default(),
+            report_ids: Model::ALL.iter().map(|m| (m.id().to_string(), 0)).collect(),
+        }
+    }
+}
+
+impl Device {
+    pub fn report_id(&self, model: Model) -> u8 {
+        self.report_ids.get(model.id()).copied().unwrap_or(0)
+    }
+}
+
+/// Prometheus text counters over HTTP.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Metrics {
+    pub enabled: bool,
+    pub address: String,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            enabled: false,
+            address: "127.0.0.1:9100".to_string(),
+        }
+    }
+}
+
+/// Read-only status queries over TCP, see `status.rs`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StatusServer {
+    pub enabled: bool,
+    pub address: String,
+}
+
+impl Default for StatusServer {
+    fn default() -> Self {
+        StatusServer {
+            enabled: false,
+            address: "127.0.0.1:9200".to_string(),
+        }
+    }
+}
+
+/// One datagram per event, see `udp.rs` for the formats.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Udp {
+    pub enabled: bool,
+    pub address: String,
+    pub format: UdpFormat,
+    /// Send a heartbeat after this long without events, 0 never.
+    pub heartbeat_ms: u32,
+}
+
+impl Default for Udp {
+    fn default() -> Self {
+        Udp {
+            enabled: false,
+            address: "127.0.0.1:9000".to_string(),
+            format: UdpFormat::Binary,
+            heartbeat_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UdpFormat {
+    /// Two bytes, kind and value.
+    #[default]
+    Binary,
+    /// The versioned JSON message of `shuttle_pro_rs::schema`.
+    Json,
+}
+
+/// Rhai script seeing every event before the bindings do.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Script {
+    /// Relative to the config file's directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// A run taking longer is aborted.
+    pub timeout_ms: u32,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Script {
+            path: None,
+            timeout_ms: 20,
+        }
+    }
+}
+
+/// MIDI output; when enabled the jog drives a relative encoder instead of keys.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Midi {
+    pub enabled: bool,
+    /// Output port number, as ordered by Windows.
+    pub device: u32,
+    /// 0-15.
+    pub channel: u8,
+    /// Controller the jog sends, 0-127.
+    pub jog_cc: u8,
+    pub relative_mode: RelativeMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Vjoy {
+    pub enabled: bool,
+    /// vJoy device number, 1-16.
+    pub device: u32,
+    /// Mirror button `b` to vJoy button `b + 1`.
+    pub buttons: bool,
+    pub jog_axis: Axis,
+    pub wheel_axis: Axis,
+}
+
+/// Kinds of toast, each with its own on/off switch and `ToastStyle`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Category {
+    ScrollSpeed,
+    Action,
+    Profile,
+    /// The target window appearing or going away.
+    Target,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Toasts {
+    pub scroll_speed: ToastStyle,
+    pub action: ToastStyle,
+    pub profile: ToastStyle,
+    pub target: ToastStyle,
+    pub error: ToastStyle,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ToastStyle {
+    pub sound: ToastSound,
+    pub duration: ToastDuration,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToastSound {
+    Silent,
+    Default,
+    Im,
+    Mail,
+    Reminder,
+    #[default]
+    Sms,
+    Alarm,
+    Call,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToastDuration {
+    #[default]
+    Short,
+    Long,
+}
+
+/// Which toasts to show, and under what title.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Notify {
+    /// "Scroll speed N" after a scroll zoom change.
+    pub scroll_speed: bool,
+    /// The `toast` text of bindings.
+    pub actions: bool,
+    /// Profile switches.
+    pub profile: bool,
+    /// The target window appearing or closing.
+    pub target: bool,
+    /// Title of info toasts, unset means the translated "Info".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Per-profile `Notify`, unset fields are inherited from the top-level one.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NotifyOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scroll_speed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Profile {
+    pub name: String,
+    /// Replaces `wheel.action` while the profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wheel_action: Option<WheelAction>,
+    /// `when_no_target` of bindings that don't set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_no_target: Option<NoTarget>,
+    #[serde(default)]
+    pub notify: NotifyOverride,
+    /// Replaces the `[target]` window match while the profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<Matcher>,
+}
+
+/// Which top-level windows are targets; every criterion set must match.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Matcher {
+    /// Window class, empty for any.
+    pub class: String,
+    /// Text the window title contains.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// File name of the owning program, e.g. `vlc.exe`, in any case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+}
+
+impl Matcher {
+    fn is_empty(&self) -> bool {
+        self.class.is_empty() && self.title.is_none() && self.process.is_none()
+    }
+
+    /// How many criteria are set.
+    fn specificity(&self) -> usize {
+        [
+            !self.class.is_empty(),
+            self.title.is_some(),
+            self.process.is_some(),
+        ]
+        .iter()
+        .filter(|set| **set)
+        .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Target {
+    /// Windows receiving keys and scroll, by default class `Qt5QWindowIcon`, VLC.
+    #[serde(flatten)]
+    pub matcher: Matcher,
+    /// Send to every window of `class` instead of the first one found.
+    pub broadcast: bool,
+    /// What to call the target in toasts and the tray tooltip.
+    pub name: String,
+    /// How often to check whether the target window exists, 0 never.
+    pub poll_ms: u32,
+    /// Ignore the device unless a target window is in the foreground.
+    pub require_foreground: bool,
+    /// Keep the `--pick-window` pick across restarts, while that window lives.
+    pub remember_pick: bool,
+    /// Send into the content window of Store app frames rather than the frame.
+    pub uwp: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Wheel {
+    /// Where horizontal scroll goes: the target window or whatever is under the mouse.
+    pub target: WheelTarget,
+    /// Scroll multiplier for ticks following each other quickly, 1 is off.
+    pub acceleration: u8,
+    /// Ticks in the same direction less than this apart count as quick.
+    pub accel_ms: u32,
+    /// Wheel ticks making up one step; fewer in a row do nothing yet.
+    pub units_per_step: u16,
+    /// What a tick does, unless the active profile says otherwise.
+    pub action: WheelAction,
+    /// Wheel units one scroll step is worth, up to 120, a mouse wheel detent.
+    pub detent_units: u16,
+    /// Units per posted wheel message, a step being sent as several
+    /// messages when this is smaller than `detent_units`.
+    pub delta: u16,
+    /// How `action = "zoom"` is sent.
+    pub zoom_with: ZoomWith,
+    /// Keys for `action = "keys"` and `"key"`.
+    pub left: Key,
+    pub right: Key,
+    /// Most keys a second `action = "key"` sends, the rest dropped.
+    pub key_rate: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoomWith {
+    /// Ctrl-flagged wheel messages posted to the wheel target.
+    #[default]
+    Post,
+    /// Ctrl held around real wheel input, to the window under the mouse.
+    Input,
+    /// Ctrl+Plus / Ctrl+Minus posted to the target.
+    Keys,
+}
+
+impl Default for Wheel {
+    fn default() -> Self {
+        Wheel {
+            target: WheelTarget::default(),
+            acceleration: 1,
+            accel_ms: 60,
+            units_per_step: 1,
+            action: WheelAction::default(),
+            zoom_with: ZoomWith::default(),
+            detent_units: 1,
+            delta: 1,
+            left: Key(VK_LEFT),
+            right: Key(VK_RIGHT),
+            key_rate: 50,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WheelAction {
+    #[default]
+    HScroll,
+    VScroll,
+    /// Ctrl + vertical scroll.
+    Zoom,
+    /// System volume up/down.
+    Volume,
+    /// `wheel.left`/`wheel.right`.
+    Keys,
+    /// `wheel.left`/`wheel.right` once per scroll step, so the scroll zoom
+    /// level multiplies them, at most `wheel.key_rate` a second.
+    Key,
+    /// A jog step per scroll step, for scrubbing.
+    Jog,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WheelTarget {
+    #[default]
+    Class,
+    UnderCursor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Jog {
+    pub left: Key,
+    pub right: Key,
+    /// Deflections beyond this count as this, 1-7.
+    pub clamp: u8,
+    /// Number of distinct positions each way after clamping, 1-7.
+    pub steps: u8,
+    /// Raw deflections up to this either way count as centre, for a ring
+    /// that rests at ±1.
+    pub center_tolerance: u8,
+    pub mode: JogMode,
+    /// For `mode = "absolute"`: scaled position (as a string, "-3" to "3" etc.) to binding.
+    pub positions: BTreeMap<String, Binding>,
+    /// For `mode = "keys"`: tier, the scaled distance from centre (as a
+    /// string, "1" up to `steps`), to modifiers held around its keys.
+    pub modifiers: BTreeMap<String, Vec<Modifier>>,
+    /// Tier (as a string, "0" up to `steps`) to binding fired when the ring
+    /// moves into it from another tier, in any mode.
+    pub tiers: BTreeMap<String, Binding>,
+    /// For `mode = "scroll"`: how often to scroll while the ring is held out.
+    pub scroll_ms: u32,
+    pub scroll_axis: ScrollAxis,
+    /// Scroll up (or left) when turning right instead.
+    pub scroll_invert: bool,
+}
+
+impl Jog {
+    /// Raw deflection `raw` with those within `center_tolerance` taken as 0.
+    pub fn centered(&self, raw: i8) -> i8 {
+        if raw.unsigned_abs() <= self.center_tolerance {
+            0
+        } else {
+            raw
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JogMode {
+    /// Seek with `left`/`right` (or MIDI) on every position change.
+    #[default]
+    Keys,
+    /// Fire the binding of the position the ring moved into.
+    Absolute,
+    /// Scroll the target as long as the ring is out, by the position's
+    /// distance from centre every `scroll_ms`.
+    Scroll,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollAxis {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Binding {
+    #[serde(flatten)]
+    pub action: Action,
+    /// Info toast shown after the action ran, either literal text or a message
+    /// id such as `playback_normal` to get it in the configured language.
+    /// Literal text may use the `PLACEHOLDERS`, e.g. `"Seek {dir} x{step}"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toast: Option<String>,
+    /// What a key action does without a target window, unset means the profile's choice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_no_target: Option<NoTarget>,
+    /// Buttons only: fire on release, press, or both.
+    #[serde(default, skip_serializing_if = "Edge::is_up")]
+    pub fire: Edge,
+    /// Buttons only: fire on press and keep firing until released.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repeat_while_held: bool,
+    /// Interval between repeats, `REPEAT_MS` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_ms: Option<u32>,
+    /// Shortest interval holding longer ramps down to; unset keeps the rate fixed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_min_ms: Option<u32>,
+    /// Percent of the interval left after each second held, `REPEAT_RAMP` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_ramp: Option<u8>,
+    /// Ignore this action again for so many ms after it fired, unset or 0 for never.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_ms: Option<u32>,
+    /// Log what this binding does in detail, down to every message posted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub log: bool,
+    /// Buttons only: don't fall through to this while a shift layer is held.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inhibit_in_layer: bool,
+    /// Buttons only: a binding of its own for the release. The rest then
+    /// fires on press whatever `fire` says, repeating if asked to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up: Option<Box<Binding>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayClick {
+    /// Open the settings window.
+    #[default]
+    Settings,
+    /// Stop acting on input until clicked again.
+    Pause,
+    /// Exit, as this used to do.
+    Quit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unmapped {
+    /// Nothing at all.
+    Ignore,
+    /// A toast naming the button, to find out which one is which.
+    Toast,
+    /// A log line naming the button.
+    #[default]
+    Log,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoTarget {
+    /// Forget it.
+    #[default]
+    Drop,
+    /// Send it, in order with others, once a target appears.
+    Queue,
+    /// Like `Queue`, but only the most recent press of the same key.
+    Latest,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    #[default]
+    Up,
+    Down,
+    Both,
+}
+
+impl Edge {
+    fn is_up(&self) -> bool {
+        *self == Edge::Up
+    }
+
+    pub fn on_down(self) -> bool {
+        self != Edge::Up
+    }
+
+    pub fn on_up(self) -> bool {
+        self != Edge::Down
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Chord {
+    pub buttons: [u16; 2],
+    #[serde(flatten)]
+    pub binding: Binding,
+}
+
+/// A chord button waiting for its partner since it went down at `since`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChordWait {
+    pub button: u16,
+    pub since: std::time::Instant,
+}
+
+/// What comes to a [`ChordWait`], see [`Config::chord_step`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChordInput {
+    Press(u16),
+    Release(u16),
+    /// The `chord_window_ms` timer went off.
+    Timer,
+}
+
+/// See [`Config::chord_step`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChordStep<'a> {
+    /// The press completes this chord.
+    Chord(&'a Binding),
+    /// The waiting button fires on its own, before the input is handled.
+    Alone,
+    /// The button keeps waiting.
+    Wait,
+}
+
+/// A global hotkey, a key with the modifiers held with it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Hotkey {
+    pub key: Key,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+/// A shift button: while it is held, `buttons` replace the other buttons'
+/// bindings. Its own binding never fires.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Layer {
+    pub button: u16,
+    /// Button index (as a string) to binding while shifted; unlisted
+    /// buttons keep their own.
+    #[serde(default)]
+    pub buttons: BTreeMap<String, Binding>,
+    /// Fired for a tap: released within `tap_ms` without another button
+    /// pressed meanwhile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shift_tap_action: Option<Binding>,
+    /// Longest press counting as a tap, `TAP_MS` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap_ms: Option<u32>,
+}
+
+/// Default `tap_ms` of a layer.
+pub const TAP_MS: u32 = 250;
+
+impl Layer {
+    pub fn tap_ms(&self) -> u32 {
+        self.tap_ms.unwrap_or(TAP_MS)
+    }
+
+    /// Whether pressing the shift button at `down` and letting go at `up`
+    /// is a tap, shorter than `tap_ms`; `used` is whether another button
+    /// went down meanwhile.
+    pub fn is_tap(&self, used: bool, down: std::time::Instant, up: std::time::Instant) -> bool {
+        let held = up.saturating_duration_since(down);
+        !used && held < std::time::Duration::from_millis(self.tap_ms() as u64)
+    }
+}
+
+/// Default interval of `repeat_while_held`.
+pub const REPEAT_MS: u32 = 100;
+/// Default `repeat_ramp`: the interval halves every second held.
+pub const REPEAT_RAMP: u8 = 50;
+
+impl Binding {
+    pub fn new(action: Action) -> Self {
+        Binding {
+            action,
+            toast: None,
+            when_no_target: None,
+            fire: Edge::Up,
+            repeat_while_held: false,
+            repeat_ms: None,
+            repeat_min_ms: None,
+            repeat_ramp: None,
+            cooldown_ms: None,
+            log: false,
+            inhibit_in_layer: false,
+            up: None,
+        }
+    }
+
+    pub fn repeat_ms(&self) -> u32 {
+        self.repeat_ms.unwrap_or(REPEAT_MS)
+    }
+
+    /// Interval between repeats once held for `held`: `repeat_ms`, shrinking
+    /// to `repeat_ramp` percent of itself per second, down to `repeat_min_ms`.
+    pub fn repeat_interval(&self, held: std::time::Duration) -> u32 {
+        let start = self.repeat_ms();
+        let Some(min) = self.repeat_min_ms.filter(|m| *m < start) else {
+            return start;
+        };
+        let ramp = self.repeat_ramp.unwrap_or(REPEAT_RAMP) as f64 / 100.0;
+        let interval = start as f64 * ramp.powf(held.as_secs_f64());
+        (interval as u32).max(min)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut buttons = BTreeMap::new();
+        for level in 0..=3 {
+            buttons.insert(
+                level.to_string(),
+                Binding::new(Action::ScrollZoom { level }),
+            );
+        }
+        buttons.insert(
+            "6".to_string(),
+            Binding::new(Action::Key { key: Key(VK_SPACE) }),
+        );
+        for b in [13, 14] {
+            let mut binding = Binding::new(Action::Key {
+                key: Key(VK_OEM_PLUS),
+            });
+            binding.toast = Some("playback_normal".to_string());
+            buttons.insert(b.to_string(), binding);
+        }
+
+        Config {
+            language: Language::default(),
+            version: CURRENT_VERSION,
+            device: Device::default(),
+            target: Target::default(),
+            wheel: Wheel::default(),
+            jog: Jog::default(),
+            buttons,
+            notify: Notify::default(),
+            toasts: Toasts::default(),
+            midi: Midi::default(),
+            vjoy: Vjoy::default(),
+            script: Script::default(),
+            udp: Udp::default(),
+            metrics: Metrics::default(),
+            status: StatusServer::default(),
+            profiles: Vec::new(),
+            profile: None,
+            max_hold_ms: 30_000,
+            idle_pause_ms: 0,
+            idle_drop_wake: false,
+            chord_window_ms: 50,
+            input_mode: InputMode::default(),
+            startup_delay_ms: 0,
+            event_log: false,
+            auto_profile: false,
+            profile_tiebreak: Tiebreak::default(),
+            rate_cap: 1000,
+            unmapped: Unmapped::default(),
+            tray_click: TrayClick::default(),
+            reload_hotkey: None,
+            chords: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+impl Default for Midi {
+    fn default() -> Self {
+        Midi {
+            enabled: false,
+            device: 0,
+            channel: 0,
+            jog_cc: 16,
+            relative_mode: RelativeMode::default(),
+        }
+    }
+}
+
+impl Default for Vjoy {
+    fn default() -> Self {
+        Vjoy {
+            enabled: false,
+            device: 1,
+            buttons: true,
+            jog_axis: Axis::X,
+            wheel_axis: Axis::Y,
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Notify {
+            scroll_speed: true,
+            actions: true,
+            profile: true,
+            target: true,
+            title: None,
+        }
+    }
+}
+
+impl Notify {
+    /// Errors are always shown.
+    pub fn enabled(&self, category: Category) -> bool {
+        match category {
+            Category::ScrollSpeed => self.scroll_speed,
+            Category::Action => self.actions,
+            Category::Profile => self.profile,
+            Category::Target => self.target,
+            Category::Error => true,
+        }
+    }
+
+    fn with(&self, o: &NotifyOverride) -> Notify {
+        Notify {
+            scroll_speed: o.scroll_speed.unwrap_or(self.scroll_speed),
+            actions: o.actions.unwrap_or(self.actions),
+            profile: o.profile.unwrap_or(self.profile),
+            target: o.target.unwrap_or(self.target),
+            title: o.title.clone().or_else(|| self.title.clone()),
+        }
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target {
+            matcher: Matcher {
+                class: "Qt5QWindowIcon".to_string(),
+                title: None,
+                process: None,
+            },
+            broadcast: false,
+            name: "VLC".to_string(),
+            poll_ms: 2000,
+            require_foreground: false,
+            remember_pick: false,
+            uwp: false,
+        }
+    }
+}
+
+impl Default for Jog {
+    fn default() -> Self {
+        Jog {
+            left: Key(VK_OEM_4),  // [
+            right: Key(VK_OEM_6), // ]
+            clamp: JOG_MAX,
+            steps: JOG_MAX,
+            center_tolerance: 0,
+            mode: JogMode::default(),
+            positions: BTreeMap::new(),
+            modifiers: BTreeMap::new(),
+            tiers: BTreeMap::new(),
+            scroll_ms: 100,
+            scroll_axis: ScrollAxis::default(),
+            scroll_invert: false,
+        }
+    }
+}
+
+impl Toasts {
+    pub fn style(&self, category: Category) -> ToastStyle {
+        match category {
+            Category::ScrollSpeed => self.scroll_speed,
+            Category::Action => self.action,
+            Category::Profile => self.profile,
+            Category::Target => self.target,
+            Category::Error => self.error,
+        }
+    }
+}
+
+impl ToastSound {
+    /// `None` plays nothing.
+    pub fn sound(self) -> Option<Sound> {
+        match self {
+            ToastSound::Silent => None,
+            ToastSound::Default => Some(Sound::Default),
+            ToastSound::Im => Some(Sound::IM),
+            ToastSound::Mail => Some(Sound::Mail),
+            ToastSound::Reminder => Some(Sound::Reminder),
+            ToastSound::Sms => Some(Sound::SMS),
+            ToastSound::Alarm => Some(Sound::Single(LoopableSound::Alarm)),
+            ToastSound::Call => Some(Sound::Single(LoopableSound::Call)),
+        }
+    }
+}
+
+impl ToastDuration {
+    pub fn duration(self) -> Duration {
+        match self {
+            ToastDuration::Short => Duration::Short,
+            ToastDuration::Long => Duration::Long,
+        }
+    }
+}
+
+impl Config {
+    pub fn button(&self, index: u16) -> Option<&Binding> {
+        self.buttons.get(&index.to_string())
+    }
+
+    /// The layer `button` shifts to, if it is a shift button.
+    pub fn layer_of(&self, button: u16) -> Option<usize> {
+        self.layers.iter().position(|l| l.button == button)
+    }
+
+    /// `button`'s binding while `layer` is held: the layer's, else its own
+    /// unless that is `inhibit_in_layer`.
+    pub fn layer_button(&self, layer: Option<usize>, button: u16) -> Option<&Binding> {
+        let Some(layer) = layer.and_then(|l| self.layers.get(l)) else {
+            return self.button(button);
+        };
+        layer
+            .buttons
+            .get(&button.to_string())
+            .or_else(|| self.button(button).filter(|b| !b.inhibit_in_layer))
+    }
+
+    /// The chord of buttons `a` and `b`, in either order.
+    pub fn chord(&self, a: u16, b: u16) -> Option<&Binding> {
+        self.chords
+            .iter()
+            .find(|c| c.buttons == [a, b] || c.buttons == [b, a])
+            .map(|c| &c.binding)
+    }
+
+    pub fn in_chord(&self, button: u16) -> bool {
+        self.chords.iter().any(|c| c.buttons.contains(&button))
+    }
+
+    /// What `input` at `now` does to `wait`. Its partner pressed within
+    /// `chord_window_ms` completes the chord. Once the window has passed,
+    /// another button is pressed, or the waiting one is released, it fires
+    /// on its own.
+    pub fn chord_step(
+        &self,
+        wait: ChordWait,
+        input: ChordInput,
+        now: std::time::Instant,
+    ) -> ChordStep<'_> {
+        let window = std::time::Duration::from_millis(self.chord_window_ms as u64);
+        let open = now.saturating_duration_since(wait.since) < window;
+        match input {
+            ChordInput::Press(b) if open => match self.chord(wait.button, b) {
+                Some(chord) => ChordStep::Chord(chord),
+                None => ChordStep::Alone,
+            },
+            _ if !open => ChordStep::Alone,
+            ChordInput::Release(b) if b == wait.button => ChordStep::Alone,
+            _ => ChordStep::Wait,
+        }
+    }
+
+    pub fn jog_position(&self, position: i8) -> Option<&Binding> {
+        self.jog.positions.get(&position.to_string())
+    }
+
+    /// Binding for the ring entering tier `tier`, the scaled distance from centre.
+    pub fn jog_tier(&self, tier: u8) -> Option<&Binding> {
+        self.jog.tiers.get(&tier.to_string())
+    }
+
+    /// Modifier keys for a jog key sent at scaled `position`, none for unlisted tiers.
+    pub fn jog_modifiers(&self, position: i8) -> Vec<VIRTUAL_KEY> {
+        let tier = position.unsigned_abs().to_string();
+        self.jog
+            .modifiers
+            .get(&tier)
+            .map_or_else(Vec::new, |m| m.iter().map(|m| m.key()).collect())
+    }
+
+    pub fn profile_index(&self, name: &str) -> Option<usize> {
+        self.profiles.iter().position(|p| p.name == name)
+    }
+
+    /// Notification settings of `profile`, falling back to the top-level ones.
+    pub fn notify(&self, profile: Option<usize>) -> Notify {
+        match profile.and_then(|i| self.profiles.get(i)) {
+            Some(p) => self.notify.with(&p.notify),
+            None => self.notify.clone(),
+        }
+    }
+
+    /// `wheel.action`, or what `profile` replaces it with.
+    pub fn wheel_action(&self, profile: Option<usize>) -> WheelAction {
+        profile
+            .and_then(|i| self.profiles.get(i))
+            .and_then(|p| p.wheel_action)
+            .unwrap_or(self.wheel.action)
+    }
+
+    /// Target windows while `profile` is active: its own match, else `[target]`'s.
+    pub fn matcher(&self, profile: Option<usize>) -> Matcher {
+        profile
+            .and_then(|i| self.profiles.get(i)?.target.clone())
+            .unwrap_or_else(|| self.target.matcher.clone())
+    }
+
+    /// The one profile to switch to out of `matching`, indices of profiles
+    /// whose `target` matched, by `profile_tiebreak`.
+    pub fn resolve_profile(&self, matching: &[usize]) -> Option<usize> {
+        let specificity = |i: &usize| {
+            let target = self.profiles.get(*i).and_then(|p| p.target.as_ref());
+            target.map_or(0, Matcher::specificity)
+        };
+        match self.profile_tiebreak {
+            Tiebreak::Order => matching.iter().min().copied(),
+            // max_by_key keeps the last of equals, so look from the back
+            Tiebreak::Specific => {
+                let mut sorted = matching.to_vec();
+                sorted.sort_unstable();
+                sorted.into_iter().rev().max_by_key(specificity)
+            }
+        }
+    }
+
+    /// `binding`'s own `when_no_target`, else `profile`'s, else drop.
+    pub fn when_no_target(&self, binding: &Binding, profile: Option<usize>) -> NoTarget {
+        binding
+            .when_no_target
+            .or_else(|| profile.and_then(|i| self.profiles.get(i)?.when_no_target))
+            .unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(1..=JOG_MAX).contains(&self.jog.clamp) || !(1..=JOG_MAX).contains(&self.jog.steps) {
+            return Err(format!("jog: clamp and steps must be 1-{}", JOG_MAX));
+        }
+        if self.jog.center_tolerance >= self.jog.clamp {
+            return Err("jog: center_tolerance must be below clamp".to_string());
+        }
+        if self.jog.scroll_ms == 0 {
+            return Err("jog: scroll_ms must be above 0".to_string());
+        }
+        if self.wheel.acceleration == 0 {
+            return Err("wheel: acceleration must be at least 1".to_string());
+        }
+        if self.wheel.units_per_step == 0 {
+            return Err("wheel: units_per_step must be at least 1".to_string());
+        }
+        let wheel = &self.wheel;
+        if !(1..=120).contains(&wheel.delta) || !(1..=120).contains(&wheel.detent_units) {
+            return Err("wheel: delta and detent_units must be 1-120".to_string());
+        }
+        // Keys go to the [target] class, never to the window under the cursor
+        let mut actions = std::iter::once(self.wheel.action)
+            .chain(self.profiles.iter().filter_map(|p| p.wheel_action));
+        if self.wheel.target == WheelTarget::UnderCursor
+            && actions.any(|a| matches!(a, WheelAction::Keys | WheelAction::Key))
+        {
+            return Err(
+                "wheel: actions \"keys\" and \"key\" can't be sent with target \"under_cursor\""
+                    .to_string(),
+            );
+        }
+        if self.wheel.key_rate == 0 {
+            return Err("wheel: key_rate must be above 0".to_string());
+        }
+        // Page 0 is undefined, and usage 0 would register for the whole page
+        if self.device.usage_page == 0 || self.device.usage == 0 {
+            return Err("device: usage_page and usage must be above 0".to_string());
+        }
+        let known = |m: &&String| Model::ALL.iter().any(|k| k.id() == m.as_str());
+        if let Some(m) = self.device.report_ids.keys().find(|m| !known(m)) {
+            return Err(format!(
+                "device: unknown model {:?} in report_ids, expected v1 or v2",
+                m
+            ));
+        }
+        if self.midi.channel > 15 || self.midi.jog_cc > 127 {
+            return Err("midi: channel must be 0-15 and jog_cc 0-127".to_string());
+        }
+        if !(1..=16).contains(&self.vjoy.device) {
+            return Err("vjoy: device must be 1-16".to_string());
+        }
+        for (i, p) in self.profiles.iter().enumerate() {
+            if self.profile_index(&p.name) != Some(i) {
+                return Err(format!("duplicate profile {:?}", p.name));
+            }
+            if p.target.as_ref().is_some_and(Matcher::is_empty) {
+                return Err(format!(
+                    "profile {:?}: target needs a class, title or process",
+                    p.name
+                ));
+            }
+        }
+        if self.matcher(None).is_empty() {
+            return Err("target: needs a class, title or process".to_string());
+        }
+        if let Some(name) = &self.profile {
+            if self.profile_index(name).is_none() {
+                return Err(format!("unknown profile {:?}", name));
+            }
+        }
+        for (index, binding) in &self.buttons {
+            match index.parse::<u16>() {
+                Ok(b) if b < BUTTON_COUNT => {}
+                _ => return Err(format!("invalid button index {:?}", index)),
+            }
+            self.validate_binding(binding)
+                .map_err(|e| format!("button {}: {}", index, e))?;
+        }
+        for chord in &self.chords {
+            let [a, b] = chord.buttons;
+            if a == b || a >= BUTTON_COUNT || b >= BUTTON_COUNT {
+                return Err(format!("invalid chord {:?}", chord.buttons));
+            }
+            self.validate_binding(&chord.binding)
+                .map_err(|e| format!("chord {:?}: {}", chord.buttons, e))?;
+        }
+        for (n, layer) in self.layers.iter().enumerate() {
+            let shift = layer.button;
+            if shift >= BUTTON_COUNT || self.layer_of(shift) != Some(n) {
+                return Err(format!(
+                    "layer {}: button {} invalid or already a shift",
+                    n, shift
+                ));
+            }
+            if self.in_chord(shift) {
+                return Err(format!(
+                    "layer {}: shift button {} can't be in a chord",
+                    n, shift
+                ));
+            }
+            for (index, binding) in &layer.buttons {
+                match index.parse::<u16>() {
+                    Ok(b) if b < BUTTON_COUNT && b != shift => {}
+                    _ => return Err(format!("layer {}: invalid button index {:?}", n, index)),
+                }
+                self.validate_binding(binding)
+                    .map_err(|e| format!("layer {} button {}: {}", n, index, e))?;
+            }
+            if let Some(tap) = &layer.shift_tap_action {
+                self.validate_binding(tap)
+                    .map_err(|e| format!("layer {} shift_tap_action: {}", n, e))?;
+            }
+        }
+        for (position, binding) in &self.jog.positions {
+            match position.parse::<i8>() {
+                Ok(p) if p.unsigned_abs() <= self.jog.steps => {}
+                _ => return Err(format!("invalid jog position {:?}", position)),
+            }
+            self.validate_binding(binding)
+                .map_err(|e| format!("jog position {}: {}", position, e))?;
+        }
+        // Modifier names are checked when parsing, into `Modifier`
+        for tier in self.jog.modifiers.keys() {
+            match tier.parse::<u8>() {
+                Ok(t) if (1..=self.jog.steps).contains(&t) => {}
+                _ => return Err(format!("invalid jog tier {:?} in jog.modifiers", tier)),
+            }
+        }
+        for (tier, binding) in &self.jog.tiers {
+            match tier.parse::<u8>() {
+                Ok(t) if t <= self.jog.steps => {}
+                _ => return Err(format!("invalid jog tier {:?} in jog.tiers", tier)),
+            }
+            self.validate_binding(binding)
+                .map_err(|e| format!("jog tier {}: {}", tier, e))?;
+        }
+        Ok(())
+    }
+
+    /// Bindings that load fine but won't behave as they read, one line each.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut found = Vec::new();
+        for (index, binding) in &self.buttons {
+            let chorded = index.parse::<u16>().is_ok_and(|b| self.in_chord(b));
+            if chorded && (binding.fire.on_down() || binding.repeat_while_held) {
+                found.push(format!(
+                    "button {} is in a chord, so its press fires up to chord_window_ms late",
+                    index
+                ));
+            }
+            if binding.repeat_while_held && binding.fire != Edge::Up {
+                found.push(format!(
+                    "button {}: repeat_while_held already fires on press, fire = {:?} is ignored",
+                    index, binding.fire
+                ));
+            }
+        }
+        for (i, chord) in self.chords.iter().enumerate() {
+            let [a, b] = chord.buttons;
+            if self.chords[..i]
+                .iter()
+                .any(|c| c.buttons == [a, b] || c.buttons == [b, a])
+            {
+                found.push(format!(
+                    "chord {:?} is defined twice, only the first is used",
+                    chord.buttons
+                ));
+            }
+        }
+        let extras = self
+            .chords
+            .iter()
+            .map(|c| (format!("chord {:?}", c.buttons), &c.binding))
+            .chain(
+                self.jog
+                    .positions
+                    .iter()
+                    .map(|(p, b)| (format!("jog position {}", p), b)),
+            )
+            .chain(
+                self.jog
+                    .tiers
+                    .iter()
+                    .map(|(t, b)| (format!("jog tier {}", t), b)),
+            );
+        for (name, binding) in extras {
+            if binding.repeat_while_held || binding.fire != Edge::Up {
+                found.push(format!(
+                    "{}: repeat_while_held and fire only apply to buttons",
+                    name
+                ));
+            }
+        }
+        found
+    }
+
+    /// Refuse bindings for buttons from `count` up, which the connected model lacks.
+    pub fn check_buttons(&self, count: u16) -> std::result::Result<(), String> {
+        let bound = self.buttons.keys().filter_map(|b| b.parse::<u16>().ok());
+        let chorded = self.chords.iter().flat_map(|c| c.buttons);
+        match bound.chain(chorded).filter(|b| *b >= count).max() {
+            Some(b) => Err(format!(
+                "button {} is mapped, but there are only 0-{}",
+                b,
+                count - 1
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Check one binding as `validate` does, also for those a script returns.
+    pub fn validate_binding(&self, binding: &Binding) -> std::result::Result<(), String> {
+        if binding.repeat_ms == Some(0) || binding.repeat_min_ms == Some(0) {
+            return Err("repeat_ms and repeat_min_ms must be above 0".to_string());
+        }
+        if binding.repeat_ramp.is_some_and(|r| !(1..=100).contains(&r)) {
+            return Err("repeat_ramp must be 1-100".to_string());
+        }
+        if let Some(up) = &binding.up {
+            if up.up.is_some() || up.repeat_while_held {
+                return Err("up: can't have its own up or repeat".to_string());
+            }
+            self.validate_binding(up)
+                .map_err(|e| format!("up: {}", e))?;
+        }
+        let toast = binding.toast.as_deref().unwrap_or_default();
+        if let Some(name) = placeholders(toast).find(|n| !PLACEHOLDERS.contains(n)) {
+            return Err(format!(
+                "unknown placeholder {{{}}} in toast {:?}",
+                name, toast
+            ));
+        }
+        match &binding.action {
+            Action::ScrollZoom { level } if *level > 7 => {
+                Err(format!("scroll zoom level {} > 7", level))
+            }
+            Action::AppCommand { command } if command.0 > 0x0FFF => {
+                Err(format!("app command {} > 4095", command.0))
+            }
+            Action::Sequence { keys } if keys.is_empty() => Err("empty key sequence".to_string()),
+            Action::OutputReport { report, .. } if report.is_empty() => {
+                Err("empty output report".to_string())
+            }
+            Action::Command { program, .. } if program.is_empty() => {
+                Err("empty command".to_string())
+            }
+            Action::OpenUrl { url } if !action::looks_like_url(url) => {
+                Err(format!("{:?} is not a URL", url))
+            }
+            Action::Profile { name } if self.profile_index(name).is_none() => {
+                Err(format!("unknown profile {:?}", name))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Names a binding's `toast` text may use as `{name}`, filled in when it fires.
+pub const PLACEHOLDERS: [&str; 5] = ["button", "value", "dir", "profile", "step"];
+
+/// Names of the `{name}` placeholders in `template`, in order.
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|part| {
+        let (name, _) = part.split_once('}')?;
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+/// `template` with each known placeholder replaced by `value(name)`;
+/// unknown ones, and braces that aren't placeholders, are kept as written.
+pub fn fill(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after
+            .split_once('}')
+            .and_then(|(name, tail)| Some((value(name)?, tail)))
+        {
+            Some((text, tail)) => {
+                out.push_str(&text);
+                rest = tail;
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Environment variable naming the config file, overriding the search.
+pub const ENV_VAR: &str = "SHUTTLE_PRO_CONFIG";
+
+/// Places searched for the config, most preferred first.
+fn candidates() -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    if let Some(appdata) = env::var_os("APPDATA") {
+        paths.push(Path::new(&appdata).join("shuttle-pro").join("config.toml"));
+    }
+    let exe = env::current_exe().map_err(|e| app_error(&e.to_string()))?;
+    paths.push(exe.with_file_name("config.toml"));
+    Ok(paths)
+}
+
+/// Pick the config file: `$SHUTTLE_PRO_CONFIG` if set, else the first of
+/// `%APPDATA%\shuttle-pro\config.toml` and `config.toml` next to the
+/// executable that exists. If there is none, a default is written to the
+/// most preferred location.
+pub fn locate() -> Result<PathBuf> {
+    let path = match env::var_os(ENV_VAR) {
+        Some(path) => {
+            println!("Config from {}", ENV_VAR);
+            PathBuf::from(path)
+        }
+        None => {
+            let candidates = candidates()?;
+            match candidates.iter().find(|p| p.exists()) {
+                Some(path) => path.clone(),
+                None => candidates[0].clone(),
+            }
+        }
+    };
+
+    if !path.exists() {
+        match write_default(&path) {
+            Ok(()) => println!("Wrote default config to {}", path.display()),
+            Err(err) => println!("Unable to write {}: {}", path.display(), err),
+        }
+    }
+    println!("Using config {}", path.display());
+    Ok(path)
+}
+
+/// The built-in mapping as a commented file, parsing to `Config::default()`.
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// Write the default config to `path`, creating its directory.
+pub fn write_default(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, DEFAULT_CONFIG)
+}
+
+/// Write `config` to the top layer of `paths`, replacing the file and any
+/// comments in it. With layers below it only its own part is written: the
+/// keys it had, and those where `config` differs from what the layers below
+/// give, so an overlay stays an overlay.
+pub fn save(paths: &[PathBuf], config: &Config) -> io::Result<()> {
+    let Some((path, below)) = paths.split_last() else {
+        return Ok(());
+    };
+    let mut value = toml::Value::try_from(config).map_err(io::Error::other)?;
+    if !below.is_empty() {
+        let failed = |e: windows::core::Error| io::Error::other(e.message().to_string());
+        let mut lower = toml::Value::Table(Default::default());
+        for layer in below {
+            merge(&mut lower, read_layer(layer).map_err(failed)?);
+        }
+        // With the defaults filled in, as they are below the lowest layer
+        let lower: Config = lower.try_into().map_err(io::Error::other)?;
+        let lower = toml::Value::try_from(lower).map_err(io::Error::other)?;
+        let own = if path.exists() {
+            Some(read_layer(path).map_err(failed)?)
+        } else {
+            None
+        };
+        value = layer_diff(value, &lower, own.as_ref())
+            .unwrap_or_else(|| toml::Value::Table(Default::default()));
+    }
+    let body = toml::to_string_pretty(&value).map_err(io::Error::other)?;
+    fs::write(
+        path,
+        format!("# Written by the settings window.\n\n{}", body),
+    )?;
+    println!("Saved config to {}", path.display());
+    Ok(())
+}
+
+/// What of `new` a layer over `lower` needs: what differs, and whatever
+/// `own`, the layer as it was, already set. Tables are compared key by key
+/// and bindings whole, as `merge` combines them.
+fn layer_diff(
+    new: toml::Value,
+    lower: &toml::Value,
+    own: Option<&toml::Value>,
+) -> Option<toml::Value> {
+    match (new, lower) {
+        (toml::Value::Table(new), toml::Value::Table(lower)) if !new.contains_key("type") => {
+            let own = own.and_then(|o| o.as_table());
+            let table: toml::value::Table = new
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let mine = own.and_then(|o| o.get(&key));
+                    match lower.get(&key) {
+                        Some(below) => layer_diff(value, below, mine).map(|v| (key, v)),
+                        None => Some((key, value)),
+                    }
+                })
+                .collect();
+            (!table.is_empty() || own.is_some()).then_some(toml::Value::Table(table))
+        }
+        (new, lower) => (own.is_some() || new != *lower).then_some(new),
+    }
+}
+
+/// Back `path` up to `<path>.bak`, if it exists, and write the defaults over it.
+pub fn reset(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(path, &backup)?;
+        println!("Backed up config to {}", Path::new(&backup).display());
+    }
+    write_default(path)
+}
+
+/// `--init`: write the default config where `locate` looks first, keeping
+/// an existing file unless `force`.
+pub fn init(force: bool) -> Result<PathBuf> {
+    let path = match env::var_os(ENV_VAR) {
+        Some(path) => PathBuf::from(path),
+        None => candidates()?.swap_remove(0),
+    };
+    if path.exists() && !force {
+        return Err(app_error(&tr(Msg::ConfigExists, &[&path.display()])));
+    }
+    write_default(&path).map_err(|e| app_error(&format!("{}: {}", path.display(), e)))?;
+    println!("Wrote default config to {}", path.display());
+    Ok(path)
+}
+
+/// The config as TOML, headed by the files it came from, lowest layer
+/// first, and the active profile.
+pub fn dump(config: &Config, paths: &[PathBuf], profile: Option<&str>) -> String {
+    let body = toml::to_string_pretty(config).unwrap_or_else(|e| format!("# {}\n", e));
+    let mut head = String::from("# Effective config, built-in defaults overridden by\n");
+    for path in paths {
+        head.push_str(&format!("#   {}\n", path.display()));
+    }
+    format!(
+        "{}# Active profile: {}\n\n{}",
+        head,
+        profile.unwrap_or("(none)"),
+        body
+    )
+}
+
+/// Upgrade a parsed config file to `CURRENT_VERSION` before deserializing it.
+/// Files without `version` are version 1; newer versions than this build
+/// knows are refused rather than half understood.
+///
+/// - v2: `wheel_target` moved to `wheel.target`.
+pub fn migrate(mut raw: toml::Value) -> std::result::Result<toml::Value, String> {
+    let table = raw.as_table_mut().ok_or("config is not a table")?;
+    let version = match table.get("version") {
+        None => 1,
+        Some(v) => v.as_integer().ok_or("version must be an integer")?,
+    };
+    if version > CURRENT_VERSION {
+        println!("Config version {} > {}, refusing", version, CURRENT_VERSION);
+        return Err(format!(
+            "config version {} is newer than this program understands ({})",
+            version, CURRENT_VERSION
+        ));
+    }
+
+    if version < 2 {
+        if let Some(target) = table.remove("wheel_target") {
+            let wheel = table
+                .entry("wheel")
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            if let Some(wheel) = wheel.as_table_mut() {
+                wheel.entry("target").or_insert(target);
+            }
+        }
+    }
+
+    if version < CURRENT_VERSION {
+        println!("Migrated config from version {}", version);
+    }
+    table.insert("version".to_string(), toml::Value::Integer(CURRENT_VERSION));
+    Ok(raw)
+}
+
+/// Load and validate the config at `path`, or the built-in mapping if there is no file.
+pub fn load(path: &Path) -> Result<Config> {
+    load_layers(&[path.to_path_buf()])
+}
+
+/// Read and migrate one config file, without deserializing it yet.
+fn read_layer(path: &Path) -> Result<toml::Value> {
+    let text =
+        fs::read_to_string(path).map_err(|e| app_error(&format!("{}: {}", path.display(), e)))?;
+    let raw: toml::Value =
+        toml::from_str(&text).map_err(|e| app_error(&format!("{}: {}", path.display(), e)))?;
+    migrate(raw).map_err(|e| app_error(&format!("{}: {}", path.display(), e)))
+}
+
+/// Merge `over` into `base`: tables key by key, at every depth; anything
+/// else, arrays included, replaced whole. So are tables with a `type`,
+/// i.e. bindings, so one's fields don't leak into an overriding action.
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(base), toml::Value::Table(over)) if !over.contains_key("type") => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(old) => merge(old, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Read `paths` as layers, each file overriding the ones before it key by
+/// key, merged but not yet deserialized. Layers must all exist; a single
+/// file that doesn't gives no keys, so the built-in mapping.
+fn read_layers(paths: &[PathBuf]) -> Result<toml::Value> {
+    let mut raw = toml::Value::Table(Default::default());
+    if let [path] = paths {
+        if !path.exists() {
+            println!("No config at {}, using defaults", path.display());
+            return Ok(raw);
+        }
+    }
+    for path in paths {
+        merge(&mut raw, read_layer(path)?);
+    }
+    println!("Loaded config from {}", layer_names(paths));
+    Ok(raw)
+}
+
+fn layer_names(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// Deserialize and validate merged layers over the built-in defaults.
+fn from_layers(raw: toml::Value) -> std::result::Result<Config, String> {
+    let config: Config = raw.try_into().map_err(|e| e.to_string())?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Load `paths` as layers over the built-in defaults and validate the
+/// result, see `read_layers`.
+pub fn load_layers(paths: &[PathBuf]) -> Result<Config> {
+    let raw = read_layers(paths)?;
+    from_layers(raw).map_err(|e| app_error(&format!("{}: {}", layer_names(paths), e)))
+}
+
+/// Swap merged layers `raw` in for `running` once they deserialize and
+/// validate, returning the config they replace. If they don't, `running`,
+/// the last good config, is left as it was.
+pub fn swap_in(running: &mut Config, raw: toml::Value) -> std::result::Result<Config, String> {
+    let config = from_layers(raw)?;
+    Ok(std::mem::replace(running, config))
+}
+
+/// Load `paths` again into `running` with `swap_in`, returning the config
+/// replaced.
+pub fn reload(running: &mut Config, paths: &[PathBuf]) -> Result<Config> {
+    let raw = read_layers(paths)?;
+    swap_in(running, raw).map_err(|e| app_error(&format!("{}: {}", layer_names(paths), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn layer() -> Layer {
+        Layer {
+            button: 0,
+            buttons: BTreeMap::new(),
+            shift_tap_action: None,
+            tap_ms: None,
+        }
+    }
+
+    #[test]
+    fn shift_tap_only() {
+        let down = Instant::now();
+        assert!(layer().is_tap(false, down, down + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn shift_hold_then_other_is_no_tap() {
+        let down = Instant::now();
+        assert!(!layer().is_tap(true, down, down + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn shift_hold_release_nothing_is_no_tap() {
+        let down = Instant::now();
+        let after = |ms| down + Duration::from_millis(ms);
+        assert!(layer().is_tap(false, down, after(TAP_MS as u64 - 1)));
+        assert!(!layer().is_tap(false, down, after(TAP_MS as u64)));
+        let layer = Layer {
+            tap_ms: Some(1000),
+            ..layer()
+        };
+        assert!(layer.is_tap(false, down, after(999)));
+        assert!(!layer.is_tap(false, down, after(1000)));
+    }
+
+    #[test]
+    fn jog_centered_tolerance_edges() {
+        let jog = Jog {
+            center_tolerance: 1,
+            ..Jog::default()
+        };
+        assert_eq!(jog.centered(0), 0);
+        assert_eq!(jog.centered(1), 0);
+        assert_eq!(jog.centered(-1), 0);
+        assert_eq!(jog.centered(2), 2);
+        assert_eq!(jog.centered(-2), -2);
+        let strict = Jog {
+            center_tolerance: 0,
+            ..Jog::default()
+        };
+        assert_eq!(strict.centered(1), 1);
+    }
+
+    #[test]
+    fn layer_button_inhibited_vs_normal() {
+        let normal = Binding::new(Action::ScrollZoom { level: 1 });
+        let inhibited = Binding {
+            inhibit_in_layer: true,
+            ..Binding::new(Action::ScrollZoom { level: 2 })
+        };
+        let config = Config {
+            buttons: BTreeMap::from([
+                ("1".to_string(), normal.clone()),
+                ("2".to_string(), inhibited.clone()),
+            ]),
+            layers: vec![layer()],
+            ..Config::default()
+        };
+        assert_eq!(config.layer_button(None, 1), Some(&normal));
+        assert_eq!(config.layer_button(None, 2), Some(&inhibited));
+        assert_eq!(config.layer_button(Some(0), 1), Some(&normal));
+        assert_eq!(config.layer_button(Some(0), 2), None);
+    }
+
+    #[test]
+    fn migrate_v1_sample() {
+        let raw: toml::Value = toml::from_str(
+            r#"
+            wheel_target = "under_cursor"
+
+            [wheel]
+            delta = 60
+            "#,
+        )
+        .unwrap();
+        let config: Config = migrate(raw).unwrap().try_into().unwrap();
+        assert_eq!(config.version, CURRENT_VERSION);
+        assert_eq!(config.wheel.target, WheelTarget::UnderCursor);
+        assert_eq!(config.wheel.delta, 60);
+    }
+
+    #[test]
+    fn migrate_refuses_newer_version() {
+        let raw = toml::Value::Table(toml::map::Map::from_iter([(
+            "version".to_string(),
+            toml::Value::Integer(CURRENT_VERSION + 1),
+        )]));
+        assert!(migrate(raw).is_err());
+    }
+
+    #[test]
+    fn repeat_interval_ramps_down_to_min() {
+        let fixed = Binding {
+            repeat_ms: Some(200),
+            ..Binding::new(Action::ScrollZoomBack)
+        };
+        assert_eq!(fixed.repeat_interval(Duration::from_secs(5)), 200);
+        let ramped = Binding {
+            repeat_min_ms: Some(40),
+            ..fixed
+        };
+        assert_eq!(ramped.repeat_interval(Duration::ZERO), 200);
+        assert_eq!(ramped.repeat_interval(Duration::from_secs(1)), 100);
+        assert_eq!(ramped.repeat_interval(Duration::from_secs(2)), 50);
+        assert_eq!(ramped.repeat_interval(Duration::from_secs(3)), 40);
+        let slow = Binding {
+            repeat_ramp: Some(80),
+            ..ramped
+        };
+        assert_eq!(slow.repeat_interval(Duration::from_secs(1)), 160);
+    }
+
+    #[test]
+    fn broken_reload_keeps_good_config() {
+        let layers = |text: &str| toml::from_str::<toml::Value>(text).unwrap();
+        let mut running = Config::default();
+        swap_in(&mut running, layers("[wheel]\ndelta = 60\n")).unwrap();
+        assert_eq!(running.wheel.delta, 60);
+        for broken in ["[wheel]\ndelta = 0\n", "[wheel]\ndelta = \"fast\"\n"] {
+            assert!(swap_in(&mut running, layers(broken)).is_err());
+            assert_eq!(running.wheel.delta, 60);
+        }
+        let old = swap_in(&mut running, layers("[wheel]\ndelta = 30\n")).unwrap();
+        assert_eq!((old.wheel.delta, running.wheel.delta), (60, 30));
+    }
+
+    #[test]
+    fn chord_window_and_timeout() {
+        let chord = Binding::new(Action::ScrollZoom { level: 3 });
+        let config = Config {
+            chords: vec![Chord {
+                buttons: [4, 5],
+                binding: chord.clone(),
+            }],
+            chord_window_ms: 50,
+            ..Config::default()
+        };
+        let wait = ChordWait {
+            button: 4,
+            since: std::time::Instant::now(),
+        };
+        let at = |ms| wait.since + Duration::from_millis(ms);
+        let step = |input, ms| config.chord_step(wait, input, at(ms));
+        // Inside the window the partner completes the chord
+        assert_eq!(step(ChordInput::Press(5), 0), ChordStep::Chord(&chord));
+        assert_eq!(step(ChordInput::Press(5), 49), ChordStep::Chord(&chord));
+        assert_eq!(step(ChordInput::Press(6), 10), ChordStep::Alone);
+        assert_eq!(step(ChordInput::Timer, 49), ChordStep::Wait);
+        assert_eq!(step(ChordInput::Release(6), 10), ChordStep::Wait);
+        // Expired: the partner comes too late
+        assert_eq!(step(ChordInput::Press(5), 50), ChordStep::Alone);
+        assert_eq!(step(ChordInput::Timer, 50), ChordStep::Alone);
+        assert_eq!(step(ChordInput::Release(6), 60), ChordStep::Alone);
+        // Released early, before a partner came
+        assert_eq!(step(ChordInput::Release(4), 10), ChordStep::Alone);
+    }
+}
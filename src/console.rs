@@ -0,0 +1,54 @@
+//! `--console`: a console window for the log, which otherwise goes
+//! nowhere, with the last report kept up to date in its title.
+
+use shuttle_pro_rs::ContourHidEvent;
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{BOOL, LPARAM, TRUE, WPARAM};
+use windows::Win32::System::Console::{
+    AllocConsole, FreeConsole, SetConsoleCtrlHandler, SetConsoleTitleW, CTRL_CLOSE_EVENT,
+};
+use windows::Win32::UI::WindowsAndMessaging::{PostMessageW, WM_CLOSE};
+
+static mut OPEN: bool = false;
+
+/// Open the console; stdout is looked up per write, so `println!` goes
+/// there from now on.
+pub fn open() {
+    if !unsafe { AllocConsole() }.as_bool() {
+        println!("Unable to open a console");
+        return;
+    }
+    unsafe {
+        OPEN = true;
+        SetConsoleCtrlHandler(Some(ctrl), true);
+        SetConsoleTitleW(&HSTRING::from("ShuttlePRO"));
+    }
+}
+
+/// Closing the console would end the process on the spot, so ask the
+/// window to close and give the message loop time to wind down.
+unsafe extern "system" fn ctrl(event: u32) -> BOOL {
+    PostMessageW(crate::MAIN_WND, WM_CLOSE, WPARAM(0), LPARAM(0));
+    if event == CTRL_CLOSE_EVENT {
+        std::thread::sleep(std::time::Duration::from_secs(2));
+    }
+    TRUE
+}
+
+/// Show `report` in the title, if the console is open.
+pub fn show(report: &ContourHidEvent) {
+    if !unsafe { OPEN } {
+        return;
+    }
+    let title = format!(
+        "ShuttlePRO  jog {:+}  wheel {:3}  keys {:015b}",
+        report.jog, report.wheel, report.keys
+    );
+    unsafe { SetConsoleTitleW(&HSTRING::from(title)) };
+}
+
+pub fn close() {
+    if unsafe { std::ptr::replace(&raw mut OPEN, false) } {
+        unsafe { FreeConsole() };
+    }
+}
@@ -0,0 +1,77 @@
+//! `--diagnose`: capture for a while, then write everything a bug report
+//! needs into one text file. Nothing is redacted; the report is made of
+//! local hardware data, the config and window titles, all listed at its top.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use shuttle_pro_rs::ContourEvents;
+
+/// What the report holds, in order, written at its top.
+pub const SECTIONS: [&str; 5] = [
+    "Build: program version and enabled cargo features",
+    "Device: model, raw input handle and interface path of the ShuttlePRO",
+    "Target: configured class and every matching window with its title",
+    "Config: the effective config, after migration and defaults",
+    "Input: every raw 6-byte report and the events decoded from it, timestamped",
+];
+
+struct Capture {
+    start: Instant,
+    input: String,
+}
+
+static mut CAPTURE: Option<Capture> = None;
+
+/// Start logging input for the report.
+pub fn start() {
+    unsafe {
+        CAPTURE = Some(Capture {
+            start: Instant::now(),
+            input: String::new(),
+        })
+    };
+}
+
+/// Log a raw report and what it decoded to, if capturing.
+pub fn input(report: &[u8], events: &[ContourEvents]) {
+    let Some(capture) = (unsafe { crate::global(&raw mut CAPTURE).as_mut() }) else {
+        return;
+    };
+    let ms = capture.start.elapsed().as_millis();
+    let _ = writeln!(capture.input, "{:>8} ms  {:02X?}  {:?}", ms, report, events);
+}
+
+/// Where the report goes: the temp directory, named by the time it was taken.
+pub fn report_path() -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    std::env::temp_dir().join(format!("shuttle-pro-diagnose-{}.txt", secs))
+}
+
+/// Stop capturing and write the report to `path`; `sections` are the ones
+/// from [`SECTIONS`] but the last, which comes from the capture.
+pub fn finish(path: &Path, sections: [String; 4]) -> io::Result<()> {
+    let capture = unsafe { std::ptr::replace(&raw mut CAPTURE, None) };
+    let mut out = String::from("ShuttlePRO diagnostic report\n\nThis file contains:\n");
+    for title in SECTIONS {
+        let _ = writeln!(out, "- {}", title);
+    }
+    let input = match &capture {
+        Some(c) if !c.input.is_empty() => c.input.clone(),
+        _ => "(no input during the capture)\n".to_string(),
+    };
+    let bodies = sections.into_iter().chain(std::iter::once(input));
+    for (title, body) in SECTIONS.iter().zip(bodies) {
+        let name = title.split(':').next().unwrap_or(title);
+        let _ = write!(out, "\n== {} ==\n\n{}", name, body);
+        if !body.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    fs::write(path, out)
+}
@@ -0,0 +1,70 @@
+//! `event_log = true`: copies of notable events in the Windows Event Log,
+//! under the source `ShuttlePRO`, for machines whose logs are collected.
+//!
+//! The source isn't registered, which would take admin rights, so Event
+//! Viewer shows each entry's text after a note that its id is unknown.
+//!
+//! Entries go through `ReportEventW`, not `ReportEventA`: the A function
+//! reads the text in the ANSI code page, which would garble the Polish
+//! messages on most machines.
+
+use windows::core::{w, HSTRING, PCWSTR};
+use windows::Win32::Foundation::PSID;
+use windows::Win32::System::EventLog::{
+    DeregisterEventSource, EventSourceHandle, RegisterEventSourceW, ReportEventW,
+    EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+static mut SOURCE: Option<EventSourceHandle> = None;
+
+/// Open or close the event source; failing to open only costs the log.
+pub fn enable(on: bool) {
+    match (on, unsafe { SOURCE }) {
+        (true, None) => match unsafe { RegisterEventSourceW(PCWSTR::null(), w!("ShuttlePRO")) } {
+            Ok(source) => unsafe { SOURCE = Some(source) },
+            Err(err) => println!("Unable to open the event log: {}", err),
+        },
+        (false, Some(source)) => unsafe {
+            DeregisterEventSource(source);
+            SOURCE = None;
+        },
+        _ => {}
+    }
+}
+
+/// Write `text` at `level`, if the event log is open. The event id is the
+/// level, 1 to 3, for filtering.
+pub fn write(level: Level, text: &str) {
+    let Some(source) = (unsafe { SOURCE }) else {
+        return;
+    };
+    let (kind, id) = match level {
+        Level::Info => (EVENTLOG_INFORMATION_TYPE, 1),
+        Level::Warning => (EVENTLOG_WARNING_TYPE, 2),
+        Level::Error => (EVENTLOG_ERROR_TYPE, 3),
+    };
+    let text = HSTRING::from(text);
+    let strings = [PCWSTR(text.as_ptr())];
+    let ok = unsafe {
+        ReportEventW(
+            source,
+            kind,
+            0,
+            id,
+            PSID::default(),
+            0,
+            Some(&strings),
+            None,
+        )
+    };
+    if !ok.as_bool() {
+        println!("Unable to write to the event log");
+    }
+}
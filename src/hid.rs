@@ -0,0 +1,260 @@
+//! Report layout from the device's HID report descriptor, as an
+//! alternative to the fixed `ContourHidEvent` byte layout.
+//!
+//! The jog is the input value that can go negative, the wheel the one that
+//! can't, and the keys the Button page usages, numbered from the lowest.
+//!
+//! Also opens a device by its interface path, for reading reports and
+//! writing output reports to it directly.
+
+use std::mem;
+
+use shuttle_pro_rs::ContourHidEvent;
+use windows::core::{Error, Result, PCWSTR};
+use windows::Win32::Devices::HumanInterfaceDevice::{
+    HidD_FreePreparsedData, HidD_GetPreparsedData, HidD_SetOutputReport, HidP_GetButtonCaps,
+    HidP_GetCaps, HidP_GetUsageValue, HidP_GetUsages, HidP_GetValueCaps, HidP_Input,
+    HIDP_BUTTON_CAPS, HIDP_CAPS, HIDP_VALUE_CAPS,
+};
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, WriteFile, FILE_ACCESS_RIGHTS, FILE_FLAGS_AND_ATTRIBUTES, FILE_GENERIC_WRITE,
+    FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows::Win32::UI::Input::{GetRawInputDeviceInfoA, RIDI_PREPARSEDDATA};
+
+use crate::i18n::Msg;
+use crate::{app_error, tr};
+
+/// HID Button usage page.
+const PAGE_BUTTON: u16 = 0x09;
+
+/// A value field of the input report.
+#[derive(Debug, Copy, Clone)]
+struct Value {
+    page: u16,
+    usage: u16,
+    bits: u16,
+    signed: bool,
+}
+
+/// Where the jog, wheel and keys are, by usage.
+pub struct Layout {
+    /// Opaque to us, but read as structures by the HID parser, so aligned.
+    preparsed: Vec<u64>,
+    jog: Value,
+    wheel: Value,
+    /// Usage of key bit 0, and how many keys there are.
+    buttons: (u16, u16),
+}
+
+impl Layout {
+    fn data(&self) -> isize {
+        self.preparsed.as_ptr() as isize
+    }
+
+    /// Decode `report` by the descriptor, keeping `fixed`'s id and padding;
+    /// `None` if a field can't be read from it.
+    pub fn decode(&self, report: &[u8], fixed: ContourHidEvent) -> Option<ContourHidEvent> {
+        let jog = self.value(self.jog, report)?;
+        let wheel = self.value(self.wheel, report)?;
+
+        let mut usages = vec![0u16; self.buttons.1 as usize];
+        let mut count = usages.len() as u32;
+        let mut report = report.to_vec();
+        unsafe {
+            HidP_GetUsages(
+                HidP_Input,
+                PAGE_BUTTON,
+                0,
+                usages.as_mut_ptr(),
+                &mut count,
+                self.data(),
+                &mut report,
+            )
+        }
+        .ok()?;
+        let keys = usages[..count as usize]
+            .iter()
+            .filter_map(|u| u.checked_sub(self.buttons.0))
+            .filter(|bit| *bit < 16)
+            .fold(0u16, |keys, bit| keys | 1 << bit);
+
+        Some(ContourHidEvent {
+            jog: jog as i8,
+            wheel: wheel as u8,
+            keys,
+            ..fixed
+        })
+    }
+
+    fn value(&self, field: Value, report: &[u8]) -> Option<i32> {
+        let mut raw = 0u32;
+        unsafe {
+            HidP_GetUsageValue(
+                HidP_Input,
+                field.page,
+                0,
+                field.usage,
+                &mut raw,
+                self.data(),
+                report,
+            )
+        }
+        .ok()?;
+        // Signed fields come back as their raw bits
+        let shift = 32 - field.bits.clamp(1, 32) as u32;
+        Some(if field.signed {
+            ((raw << shift) as i32) >> shift
+        } else {
+            raw as i32
+        })
+    }
+}
+
+/// Read the layout of raw input device `dev`, logging what was found;
+/// `None` when the descriptor doesn't have the expected fields.
+pub fn layout(dev: HANDLE) -> Option<Layout> {
+    let mut size = 0u32;
+    unsafe { GetRawInputDeviceInfoA(dev, RIDI_PREPARSEDDATA, None, &mut size) };
+    if size == 0 {
+        return None;
+    }
+    let mut preparsed = vec![0u64; (size as usize).div_ceil(8)];
+    let rc = unsafe {
+        GetRawInputDeviceInfoA(
+            dev,
+            RIDI_PREPARSEDDATA,
+            Some(preparsed.as_mut_ptr() as *mut ::core::ffi::c_void),
+            &mut size,
+        )
+    };
+    if rc < 1 || rc > size {
+        return None;
+    }
+    let data = preparsed.as_ptr() as isize;
+
+    let mut caps: HIDP_CAPS = unsafe { mem::zeroed() };
+    unsafe { HidP_GetCaps(data, &mut caps) }.ok()?;
+    println!(
+        "HID descriptor: usage {:04X}:{:04X}, input report {} bytes, {} value caps, {} button caps",
+        caps.UsagePage,
+        caps.Usage,
+        caps.InputReportByteLength,
+        caps.NumberInputValueCaps,
+        caps.NumberInputButtonCaps
+    );
+
+    let mut count = caps.NumberInputValueCaps;
+    let mut values: Vec<HIDP_VALUE_CAPS> = vec![unsafe { mem::zeroed() }; count as usize];
+    unsafe { HidP_GetValueCaps(HidP_Input, values.as_mut_ptr(), &mut count, data) }.ok()?;
+    let values: Vec<Value> = values[..count as usize]
+        .iter()
+        .map(|v| {
+            let value = Value {
+                page: v.UsagePage,
+                usage: unsafe {
+                    if v.IsRange.0 != 0 {
+                        v.Anonymous.Range.UsageMin
+                    } else {
+                        v.Anonymous.NotRange.Usage
+                    }
+                },
+                bits: v.BitSize,
+                signed: v.LogicalMin < 0,
+            };
+            println!(
+                "HID value: usage {:04X}:{:04X}, report {}, {} bits, {}..{}",
+                value.page, value.usage, v.ReportID, v.BitSize, v.LogicalMin, v.LogicalMax
+            );
+            value
+        })
+        .collect();
+
+    let mut count = caps.NumberInputButtonCaps;
+    let mut buttons: Vec<HIDP_BUTTON_CAPS> = vec![unsafe { mem::zeroed() }; count as usize];
+    unsafe { HidP_GetButtonCaps(HidP_Input, buttons.as_mut_ptr(), &mut count, data) }.ok()?;
+    let buttons = buttons[..count as usize]
+        .iter()
+        .filter(|b| b.UsagePage == PAGE_BUTTON && b.IsRange.0 != 0)
+        .map(|b| unsafe { (b.Anonymous.Range.UsageMin, b.Anonymous.Range.UsageMax) })
+        .min()?;
+    println!("HID buttons: usages {}..={}", buttons.0, buttons.1);
+
+    let jog = *values.iter().find(|v| v.signed)?;
+    let wheel = *values.iter().find(|v| !v.signed)?;
+    println!(
+        "HID layout: jog {:04X}:{:04X}, wheel {:04X}:{:04X}",
+        jog.page, jog.usage, wheel.page, wheel.usage
+    );
+    Some(Layout {
+        preparsed,
+        jog,
+        wheel,
+        buttons: (buttons.0, buttons.1.saturating_sub(buttons.0) + 1),
+    })
+}
+
+/// Open the device at interface `path` with `access`, shared since Windows
+/// and other programs may have it open too.
+pub fn open_hid_device(
+    path: &[u16],
+    access: FILE_ACCESS_RIGHTS,
+    flags: FILE_FLAGS_AND_ATTRIBUTES,
+) -> Result<HANDLE> {
+    let mut path = path.to_vec();
+    path.push(0);
+    unsafe {
+        CreateFileW(
+            PCWSTR(path.as_ptr()),
+            access.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            None,
+            OPEN_EXISTING,
+            flags,
+            HANDLE(0),
+        )
+    }
+}
+
+/// Send `report`, report ID first, to the device at interface `path` as an
+/// output report, padded with zeros to the length its descriptor gives.
+pub fn write_output_report(path: &[u16], report: &[u8]) -> Result<()> {
+    let file = open_hid_device(path, FILE_GENERIC_WRITE, FILE_FLAGS_AND_ATTRIBUTES(0))?;
+    let result = write_report(file, report);
+    unsafe { CloseHandle(file) };
+    result
+}
+
+fn write_report(file: HANDLE, report: &[u8]) -> Result<()> {
+    let mut data = 0isize;
+    if !unsafe { HidD_GetPreparsedData(file, &mut data) }.as_bool() {
+        return Err(Error::from_win32());
+    }
+    let mut caps: HIDP_CAPS = unsafe { mem::zeroed() };
+    let status = unsafe { HidP_GetCaps(data, &mut caps) };
+    unsafe { HidD_FreePreparsedData(data) };
+    status?;
+
+    let size = caps.OutputReportByteLength as usize;
+    if size == 0 {
+        return Err(app_error(&tr(Msg::NoOutputReports, &[])));
+    }
+    if report.len() > size {
+        return Err(app_error(&tr(
+            Msg::OutputReportSize,
+            &[&report.len(), &size],
+        )));
+    }
+    let mut buffer = report.to_vec();
+    buffer.resize(size, 0);
+    // The interrupt OUT endpoint if there is one, else the control pipe
+    let mut written = 0u32;
+    if unsafe { WriteFile(file, Some(&buffer), Some(&mut written), None) }.as_bool() {
+        return Ok(());
+    }
+    if unsafe { HidD_SetOutputReport(file, buffer.as_ptr() as *const _, size as u32) }.as_bool() {
+        return Ok(());
+    }
+    Err(Error::from_win32())
+}
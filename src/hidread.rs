@@ -0,0 +1,310 @@
+//! `input_mode = "readfile"`: read reports from the HID device itself on a
+//! background thread, for setups where raw input doesn't reach the hidden
+//! window. Reports are posted to the window as they are, so all decoding
+//! and state stays on the window thread.
+
+use std::mem;
+use std::thread;
+use std::time::Duration;
+
+use shuttle_pro_rs::{is_device, Model, REPORT_SIZE};
+use windows::core::{Error, GUID, PCWSTR};
+use windows::Win32::Devices::DeviceAndDriverInstallation::{
+    SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInterfaces, SetupDiGetClassDevsW,
+    SetupDiGetDeviceInterfaceDetailW, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT, HDEVINFO,
+    SP_DEVICE_INTERFACE_DATA, SP_DEVICE_INTERFACE_DETAIL_DATA_W,
+};
+use windows::Win32::Devices::HumanInterfaceDevice::HidD_GetHidGuid;
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_IO_PENDING, HWND, LPARAM, WPARAM,
+};
+use windows::Win32::Storage::FileSystem::{ReadFile, FILE_FLAG_OVERLAPPED, FILE_GENERIC_READ};
+use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject, INFINITE};
+use windows::Win32::System::IO::{GetOverlappedResult, OVERLAPPED};
+use windows::Win32::UI::WindowsAndMessaging::PostMessageW;
+
+use crate::hid;
+
+/// Posted for every report: its first four bytes in wparam, the rest in lparam.
+pub const APPWM_REPORT: u32 = crate::APPWM_ICONNOTIFY + 1;
+/// Posted once the device is open, wparam being the index in `Model::ALL`.
+pub const APPWM_OPENED: u32 = crate::APPWM_ICONNOTIFY + 2;
+
+/// How long to wait before looking for the device again after it went away.
+const RETRY: Duration = Duration::from_secs(2);
+
+/// Interface path and model of the first ShuttlePRO present.
+pub fn find_device() -> Option<(Vec<u16>, Model)> {
+    find(Model::from_name)
+}
+
+/// Interface path of the first HID device present with `vid`/`pid`.
+pub fn find_interface(vid: u16, pid: u16) -> Option<Vec<u16>> {
+    find(|name| is_device(name, vid, pid).then_some(())).map(|(path, ())| path)
+}
+
+/// The first HID interface present that `wanted` maps to `Some`, by path.
+fn find<T>(wanted: impl Fn(&[u8]) -> Option<T>) -> Option<(Vec<u16>, T)> {
+    let guid = unsafe { HidD_GetHidGuid() };
+    let set = unsafe {
+        SetupDiGetClassDevsW(
+            Some(&guid),
+            PCWSTR::null(),
+            HWND(0),
+            DIGCF_PRESENT | DIGCF_DEVICEINTERFACE,
+        )
+    }
+    .ok()?;
+    let found = first_match(
+        (0..).map_while(|index| read_interface(set, &guid, index)),
+        wanted,
+    );
+    unsafe { SetupDiDestroyDeviceInfoList(set) };
+    found
+}
+
+/// One enumerated HID interface: its `SP_DEVICE_INTERFACE_DETAIL_DATA_W`
+/// in a buffer with the size reported for it, or why it has none.
+type Interface = std::result::Result<(Vec<u32>, usize), &'static str>;
+
+/// HID interface `index` of `set`; `None` past the last one.
+fn read_interface(set: HDEVINFO, guid: &GUID, index: u32) -> Option<Interface> {
+    let mut interface = SP_DEVICE_INTERFACE_DATA {
+        cbSize: mem::size_of::<SP_DEVICE_INTERFACE_DATA>() as u32,
+        ..Default::default()
+    };
+    if !unsafe { SetupDiEnumDeviceInterfaces(set, None, guid, index, &mut interface) }.as_bool() {
+        return None;
+    }
+    // The first call reports the size, the detail struct ends in the path
+    let mut size = 0u32;
+    unsafe { SetupDiGetDeviceInterfaceDetailW(set, &interface, None, 0, Some(&mut size), None) };
+    if (size as usize) < mem::size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>() {
+        return Some(Err("no detail data"));
+    }
+    let mut buffer = vec![0u32; (size as usize).div_ceil(4)];
+    let detail = buffer.as_mut_ptr() as *mut SP_DEVICE_INTERFACE_DETAIL_DATA_W;
+    unsafe { (*detail).cbSize = mem::size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>() as u32 };
+    let ok = unsafe {
+        SetupDiGetDeviceInterfaceDetailW(set, &interface, Some(detail), size, None, None)
+    };
+    if !ok.as_bool() {
+        return Some(Err("detail data unreadable"));
+    }
+    Some(Ok((buffer, size as usize)))
+}
+
+/// The path of the first of `interfaces` that `wanted` maps to `Some`, with
+/// that. One bad interface mustn't stop the search, so those are skipped.
+fn first_match<T>(
+    interfaces: impl IntoIterator<Item = Interface>,
+    wanted: impl Fn(&[u8]) -> Option<T>,
+) -> Option<(Vec<u16>, T)> {
+    for (index, interface) in interfaces.into_iter().enumerate() {
+        let (buffer, size) = match interface {
+            Ok(detail) => detail,
+            Err(why) => {
+                println!("Skipping HID interface {}: {}", index, why);
+                continue;
+            }
+        };
+        let path = detail_path(&buffer, size);
+        if let Some(found) = wanted(String::from_utf16_lossy(&path).as_bytes()) {
+            return Some((path, found));
+        }
+    }
+    None
+}
+
+/// The `DevicePath` of the `SP_DEVICE_INTERFACE_DETAIL_DATA_W` filling the
+/// first `size` bytes of `buffer`, up to its NUL. Bounded by `size` and the
+/// buffer, whichever is shorter, in case the path isn't terminated.
+fn detail_path(buffer: &[u32], size: usize) -> Vec<u16> {
+    let size = size.min(mem::size_of_val(buffer));
+    let units = buffer.iter().flat_map(|word| {
+        let [a, b, c, d] = word.to_ne_bytes();
+        [u16::from_ne_bytes([a, b]), u16::from_ne_bytes([c, d])]
+    });
+    // The path follows the u32 cbSize
+    units
+        .take(size / 2)
+        .skip(2)
+        .take_while(|unit| *unit != 0)
+        .collect()
+}
+
+/// Start reading on a background thread, posting reports to `window`.
+pub fn start(window: HWND) -> std::io::Result<()> {
+    // HWND isn't Send, the handle value is
+    let window = window.0;
+    thread::Builder::new()
+        .name("hidread".to_string())
+        .spawn(move || loop {
+            match find_device() {
+                Some((path, model)) => {
+                    read_device(HWND(window), &path, model);
+                    println!("ReadFile input ended, looking for the device again");
+                }
+                None => println!("No ShuttlePRO found for ReadFile input"),
+            }
+            thread::sleep(RETRY);
+        })?;
+    Ok(())
+}
+
+/// Read reports from the device at `path` until reading fails.
+fn read_device(window: HWND, path: &[u16], model: Model) {
+    let file = match hid::open_hid_device(path, FILE_GENERIC_READ, FILE_FLAG_OVERLAPPED) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("Unable to open {}: {}", String::from_utf16_lossy(path), err);
+            return;
+        }
+    };
+    let Ok(event) = (unsafe { CreateEventW(None, true, false, None) }) else {
+        unsafe { CloseHandle(file) };
+        return;
+    };
+    let index = Model::ALL.iter().position(|m| *m == model).unwrap_or(0);
+    unsafe { PostMessageW(window, APPWM_OPENED, WPARAM(index), LPARAM(0)) };
+    println!("Reading {} with ReadFile", model);
+
+    let mut report = [0u8; 64];
+    loop {
+        let mut overlapped = OVERLAPPED {
+            hEvent: event,
+            ..Default::default()
+        };
+        let mut read = 0u32;
+        let ok = unsafe {
+            ReadFile(
+                file,
+                Some(report.as_mut_ptr() as *mut _),
+                report.len() as u32,
+                None,
+                Some(&mut overlapped),
+            )
+        };
+        // Pending, or already done; either way the event says when. Any
+        // other failure never signals it.
+        if !ok.as_bool() && unsafe { GetLastError() } != ERROR_IO_PENDING {
+            println!("ReadFile failed: {}", Error::from_win32());
+            break;
+        }
+        unsafe { WaitForSingleObject(event, INFINITE) };
+        if !unsafe { GetOverlappedResult(file, &overlapped, &mut read, true) }.as_bool() {
+            break;
+        }
+        if (read as usize) < REPORT_SIZE {
+            continue;
+        }
+        let low = u32::from_le_bytes([report[0], report[1], report[2], report[3]]);
+        let high = u16::from_le_bytes([report[4], report[5]]);
+        unsafe {
+            PostMessageW(
+                window,
+                APPWM_REPORT,
+                WPARAM(low as usize),
+                LPARAM(high as isize),
+            )
+        };
+    }
+    unsafe {
+        CloseHandle(event);
+        CloseHandle(file);
+    }
+}
+
+/// The report packed into an `APPWM_REPORT` message.
+pub fn unpack(wparam: WPARAM, lparam: LPARAM) -> [u8; REPORT_SIZE] {
+    let low = (wparam.0 as u32).to_le_bytes();
+    let high = (lparam.0 as u16).to_le_bytes();
+    [low[0], low[1], low[2], low[3], high[0], high[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A detail buffer as SetupDiGetDeviceInterfaceDetailW fills it, zeroed
+    /// past the reported size like the one `read_interface` allocates.
+    fn detail(path: &str, nul: bool) -> (Vec<u32>, usize) {
+        let mut units = vec![0u16; 2];
+        units.extend(path.encode_utf16());
+        if nul {
+            units.push(0);
+        }
+        let size = units.len() * 2;
+        units.resize(units.len().div_ceil(2) * 2 + 2, 0);
+        let words = units
+            .chunks(2)
+            .map(|pair| {
+                let [a, b] = pair[0].to_ne_bytes();
+                let [c, d] = pair[1].to_ne_bytes();
+                u32::from_ne_bytes([a, b, c, d])
+            })
+            .collect();
+        (words, size)
+    }
+
+    #[test]
+    fn detail_path_terminated() {
+        let name = r"\\?\hid#vid_0b33&pid_0030#7&1a2b";
+        // Both parities, so the NUL either ends a word or starts one
+        for name in [name, &name[1..]] {
+            let (buffer, size) = detail(name, true);
+            assert_eq!(String::from_utf16_lossy(&detail_path(&buffer, size)), name);
+        }
+    }
+
+    #[test]
+    fn detail_path_malformed() {
+        // Unterminated: the path ends with the reported size, not the padding
+        let (buffer, size) = detail("abc", false);
+        assert_eq!(
+            detail_path(&buffer, size),
+            "abc".encode_utf16().collect::<Vec<_>>()
+        );
+        // A size beyond the buffer stops at its end, or at its zero padding
+        assert_eq!(detail_path(&buffer, 1000), detail_path(&buffer, size));
+        assert_eq!(
+            detail_path(&buffer[..2], 1000),
+            "ab".encode_utf16().collect::<Vec<_>>()
+        );
+        assert_eq!(detail_path(&buffer, 4), []);
+    }
+
+    #[test]
+    fn enumeration_skips_bad_interfaces() {
+        let shuttle = r"\\?\hid#vid_0b33&pid_0030#7&1a2b";
+        let interfaces = vec![
+            Err("no detail data"),
+            Ok(detail(r"\\?\hid#vid_046d&pid_c52b#8&3c4d", true)),
+            Err("detail data unreadable"),
+            Ok(detail("abc", false)),
+            Ok((vec![0; 4], 1000)),
+            Ok(detail(shuttle, true)),
+            Ok(detail(r"\\?\hid#vid_0b33&pid_0010#9&5e6f", true)),
+        ];
+        let (path, model) =
+            first_match(interfaces, Model::from_name).expect("found past the bad ones");
+        assert_eq!(String::from_utf16_lossy(&path), shuttle);
+        assert_eq!(model, Model::ShuttleProV2);
+
+        let none = vec![Err("no detail data"), Ok(detail("abc", true))];
+        assert!(first_match(none, Model::from_name).is_none());
+    }
+
+    #[test]
+    fn enumeration_by_vid_pid() {
+        let leds = r"\\?\hid#vid_16c0&pid_05df#8&3c4d";
+        let interfaces = vec![
+            Ok(detail(r"\\?\hid#vid_0b33&pid_0030#7&1a2b", true)),
+            Err("no detail data"),
+            Ok(detail(leds, true)),
+        ];
+        let wanted = |name: &[u8]| is_device(name, 0x16C0, 0x05DF).then_some(());
+        let (path, ()) = first_match(interfaces, wanted).expect("found");
+        assert_eq!(String::from_utf16_lossy(&path), leds);
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    En,
+    Pl,
+}
+
+/// Every user-facing string, `{}` marks where format args go, in order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Msg {
+    TitleInfo,
+    TitleError,
+    ScrollSpeed,
+    PlaybackNormal,
+    Profile,
+    RunFailed,
+    OpenFailed,
+    NotAUrl,
+    RegisterFailed,
+    ConfigWritten,
+    ConfigExists,
+    BadArgument,
+    ConfigDumped,
+    MenuSettings,
+    MenuQuit,
+    MenuReset,
+    ResetConfirm,
+    SettingsButton,
+    SettingsJogLeft,
+    SettingsJogRight,
+    SettingsSave,
+    SettingsCancel,
+    #[cfg_attr(
+        all(
+            feature = "midi",
+            feature = "vjoy",
+            feature = "net",
+            feature = "scripting"
+        ),
+        allow(dead_code)
+    )]
+    BackendDisabled,
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    ScriptFailed,
+    RecordFailed,
+    ModelMismatch,
+    TargetReady,
+    TargetClosed,
+    TipReady,
+    TipNoTarget,
+    TipPaused,
+    Paused,
+    Resumed,
+    TypeFailed,
+    ConfigWarnings,
+    DiagnoseStarted,
+    DiagnoseWritten,
+    TipBackground,
+    SelftestTitle,
+    SelftestNoTarget,
+    SelftestResult,
+    SelftestPass,
+    SelftestFail,
+    SelftestNothing,
+    PickWindow,
+    Picked,
+    PickedClosed,
+    PickCancelled,
+    ReloadFailed,
+    #[cfg_attr(not(feature = "vjoy"), allow(dead_code))]
+    VjoyMissing,
+    #[cfg_attr(not(feature = "vjoy"), allow(dead_code))]
+    VjoyDevice,
+    MenuLastEvent,
+    LastEventNone,
+    LastEvent,
+    LastEventNothing,
+    MenuReload,
+    ConfigReloaded,
+    HotkeyFailed,
+    UnmappedButton,
+    LearnStarted,
+    LearnButton,
+    LearnJog,
+    LearnWheel,
+    LearnStopped,
+    MenuStopLearning,
+    WindowFailed,
+    MenuDryRun,
+    DryRunOn,
+    DryRunOff,
+    TipDryRun,
+    ScrollZoomNoHistory,
+    TrayPaused,
+    NoOutputReports,
+    OutputReportSize,
+    OutputReportFailed,
+    NoDevice,
+    NoAssociation,
+    DdeFailed,
+}
+
+/// Messages config can refer to by name, e.g. a binding's `toast = "playback_normal"`.
+/// They take no arguments, except `scroll_speed`, given the current speed.
+const IDS: &[(&str, Msg)] = &[
+    ("scroll_speed", Msg::ScrollSpeed),
+    ("playback_normal", Msg::PlaybackNormal),
+];
+
+impl Msg {
+    pub fn from_id(id: &str) -> Option<Msg> {
+        IDS.iter().find(|(n, _)| *n == id).map(|(_, m)| *m)
+    }
+
+    fn template(self, lang: Language) -> &'static str {
+        match lang {
+            Language::En => match self {
+                Msg::TitleInfo => "Info",
+                Msg::TitleError => "Error",
+                Msg::ScrollSpeed => "Scroll speed {}",
+                Msg::PlaybackNormal => "Playback speed normal",
+                Msg::Profile => "Profile {}",
+                Msg::RunFailed => "Unable to run {}: {}",
+                Msg::OpenFailed => "Unable to open {}: {}",
+                Msg::NotAUrl => "{} is not a URL",
+                Msg::RegisterFailed => "Not receiving ShuttlePRO input: {}",
+                Msg::ConfigWritten => "Wrote default config to {}",
+                Msg::ConfigExists => "{} already exists, add --force to overwrite it",
+                Msg::BadArgument => "Unknown argument {}",
+                Msg::ConfigDumped => "Effective config written to {}",
+                Msg::MenuSettings => "Settings...",
+                Msg::MenuQuit => "Quit",
+                Msg::MenuReset => "Reset to defaults",
+                Msg::ResetConfirm => "Replace the config with the defaults? The current file is kept as config.toml.bak.",
+                Msg::SettingsButton => "Button {}",
+                Msg::SettingsJogLeft => "Jog left key",
+                Msg::SettingsJogRight => "Jog right key",
+                Msg::SettingsSave => "Save",
+                Msg::SettingsCancel => "Cancel",
+                Msg::BackendDisabled => "{} is not available in this build, it needs the \"{}\" cargo feature",
+                Msg::ScriptFailed => "Script failed: {}",
+                Msg::RecordFailed => "Unable to record to {}: {}",
+                Msg::ModelMismatch => "Config does not fit the {}: {}",
+                Msg::TargetReady => "{} ready",
+                Msg::TargetClosed => "{} closed",
+                Msg::TipReady => "Contour Control: {} ready",
+                Msg::TipNoTarget => "Contour Control: no {} window",
+                Msg::TipPaused => "Contour Control: paused",
+                Msg::Paused => "Paused after inactivity",
+                Msg::Resumed => "Resumed",
+                Msg::TypeFailed => "Unable to type {}: {}",
+                Msg::ConfigWarnings => "Check the config:\n{}",
+                Msg::DiagnoseStarted => "Capturing for {} s, use every control of the ShuttlePRO now",
+                Msg::DiagnoseWritten => "Diagnostic report written to {}",
+                Msg::TipBackground => "Contour Control: {} not in front, input ignored",
+                Msg::SelftestTitle => "Self-test",
+                Msg::SelftestNoTarget => "No {} window found; start it, then run the self-test again",
+                Msg::SelftestResult => "Sent to {}:\n{}",
+                Msg::SelftestPass => "{}: ok, {} messages posted",
+                Msg::SelftestFail => "{}: FAILED, {} posted, {} refused",
+                Msg::SelftestNothing => "{}: FAILED, nothing posted",
+                Msg::PickWindow => "Click the window to send input to, right-click to cancel",
+                Msg::Picked => "Input goes to \"{}\" until it closes",
+                Msg::PickedClosed => "Picked window closed, back to finding {}",
+                Msg::PickCancelled => "Window pick cancelled",
+                Msg::ReloadFailed => "Config not reloaded, keeping the previous one: {}",
+                Msg::VjoyMissing => "vJoy is not installed or not enabled",
+                Msg::VjoyDevice => "vJoy device {} doesn't exist or another program uses it",
+                Msg::MenuLastEvent => "Last event",
+                Msg::LastEventNone => "Nothing from the ShuttlePRO yet",
+                Msg::LastEvent => "Event: {}\nAction: {}\nTarget: {}\nMessages sent: {} ok, {} failed",
+                Msg::LastEventNothing => "none",
+                Msg::MenuReload => "Reload config",
+                Msg::ConfigReloaded => "Config reloaded",
+                Msg::HotkeyFailed => "Unable to register the reload hotkey, it may be in use by another program",
+                Msg::UnmappedButton => "Unmapped button {}",
+                Msg::LearnStarted => "Learn mode: press buttons, turn the jog or the wheel to see their numbers. Esc or the tray menu stops it.",
+                Msg::LearnButton => "Button {}",
+                Msg::LearnJog => "Jog {}",
+                Msg::LearnWheel => "Wheel {}",
+                Msg::LearnStopped => "Learn mode off, buttons act again",
+                Msg::MenuStopLearning => "Stop learning",
+                Msg::WindowFailed => "Unable to create the program's window: {}",
+                Msg::MenuDryRun => "Dry run",
+                Msg::DryRunOn => "Dry run: actions are logged, nothing is sent",
+                Msg::DryRunOff => "Dry run off after {} actions, sending again",
+                Msg::TipDryRun => "{} (dry run)",
+                Msg::ScrollZoomNoHistory => "No earlier scroll speed",
+                Msg::TrayPaused => "Paused, click the tray icon to resume",
+                Msg::NoOutputReports => "The device has no output reports",
+                Msg::OutputReportSize => "Output report of {} bytes is longer than the device's {}",
+                Msg::OutputReportFailed => "Unable to send the output report: {}",
+                Msg::NoDevice => "No HID device {} found",
+                Msg::NoAssociation => "No program is set to open this kind of link",
+                Msg::DdeFailed => "The program didn't answer the request to open it",
+            },
+            Language::Pl => match self {
+                Msg::TitleInfo => "Informacja",
+                Msg::TitleError => "Błąd",
+                Msg::ScrollSpeed => "Szybkość przewijania {}",
+                Msg::PlaybackNormal => "Normalna prędkość odtwarzania",
+                Msg::Profile => "Profil {}",
+                Msg::RunFailed => "Nie można uruchomić {}: {}",
+                Msg::OpenFailed => "Nie można otworzyć {}: {}",
+                Msg::NotAUrl => "{} nie jest adresem URL",
+                Msg::RegisterFailed => "Brak odczytu z ShuttlePRO: {}",
+                Msg::ConfigWritten => "Zapisano domyślną konfigurację do {}",
+                Msg::ConfigExists => "{} już istnieje, dodaj --force aby nadpisać",
+                Msg::BadArgument => "Nieznany argument {}",
+                Msg::ConfigDumped => "Konfigurację zapisano do {}",
+                Msg::MenuSettings => "Ustawienia...",
+                Msg::MenuQuit => "Zakończ",
+                Msg::MenuReset => "Przywróć domyślne",
+                Msg::ResetConfirm => "Zastąpić konfigurację domyślną? Obecny plik zostanie zachowany jako config.toml.bak.",
+                Msg::SettingsButton => "Przycisk {}",
+                Msg::SettingsJogLeft => "Klawisz pokrętła w lewo",
+                Msg::SettingsJogRight => "Klawisz pokrętła w prawo",
+                Msg::SettingsSave => "Zapisz",
+                Msg::SettingsCancel => "Anuluj",
+                Msg::BackendDisabled => "{} nie jest dostępne w tej wersji, wymaga funkcji cargo \"{}\"",
+                Msg::ScriptFailed => "Błąd skryptu: {}",
+                Msg::RecordFailed => "Nie można nagrywać do {}: {}",
+                Msg::ModelMismatch => "Konfiguracja nie pasuje do {}: {}",
+                Msg::TargetReady => "{} gotowy",
+                Msg::TargetClosed => "{} zamknięty",
+                Msg::TipReady => "Contour Control: {} gotowy",
+                Msg::TipNoTarget => "Contour Control: brak okna {}",
+                Msg::TipPaused => "Contour Control: wstrzymano",
+                Msg::Paused => "Wstrzymano z powodu braku aktywności",
+                Msg::Resumed => "Wznowiono",
+                Msg::TypeFailed => "Nie można wpisać {}: {}",
+                Msg::ConfigWarnings => "Sprawdź konfigurację:\n{}",
+                Msg::DiagnoseStarted => "Zbieranie danych przez {} s, użyj teraz wszystkich elementów ShuttlePRO",
+                Msg::DiagnoseWritten => "Raport diagnostyczny zapisano do {}",
+                Msg::TipBackground => "Contour Control: {} w tle, wejście ignorowane",
+                Msg::SelftestTitle => "Autotest",
+                Msg::SelftestNoTarget => "Nie znaleziono okna {}; uruchom je i powtórz autotest",
+                Msg::SelftestResult => "Wysłano do {}:\n{}",
+                Msg::SelftestPass => "{}: ok, wysłano {} komunikatów",
+                Msg::SelftestFail => "{}: BŁĄD, wysłano {}, odrzucono {}",
+                Msg::SelftestNothing => "{}: BŁĄD, nic nie wysłano",
+                Msg::PickWindow => "Kliknij okno, do którego ma trafiać sterowanie, prawym przyciskiem anulujesz",
+                Msg::Picked => "Sterowanie trafia do „{}” aż do jego zamknięcia",
+                Msg::PickedClosed => "Wybrane okno zamknięto, znów szukam {}",
+                Msg::PickCancelled => "Anulowano wybór okna",
+                Msg::ReloadFailed => "Nie wczytano konfiguracji, zostaje poprzednia: {}",
+                Msg::VjoyMissing => "vJoy nie jest zainstalowany lub włączony",
+                Msg::VjoyDevice => "Urządzenie vJoy {} nie istnieje lub używa go inny program",
+                Msg::MenuLastEvent => "Ostatnie zdarzenie",
+                Msg::LastEventNone => "ShuttlePRO jeszcze nic nie wysłał",
+                Msg::LastEvent => "Zdarzenie: {}\nAkcja: {}\nCel: {}\nWysłane komunikaty: {} udanych, {} nieudanych",
+                Msg::LastEventNothing => "brak",
+                Msg::MenuReload => "Wczytaj konfigurację ponownie",
+                Msg::ConfigReloaded => "Wczytano konfigurację ponownie",
+                Msg::HotkeyFailed => "Nie można zarejestrować skrótu przeładowania, może go używać inny program",
+                Msg::UnmappedButton => "Nieprzypisany przycisk {}",
+                Msg::LearnStarted => "Tryb nauki: naciśnij przycisk, obróć pierścień lub kółko, aby zobaczyć ich numery. Esc lub menu w zasobniku go kończy.",
+                Msg::LearnButton => "Przycisk {}",
+                Msg::LearnJog => "Pierścień {}",
+                Msg::LearnWheel => "Kółko {}",
+                Msg::LearnStopped => "Tryb nauki wyłączony, przyciski znów działają",
+                Msg::MenuStopLearning => "Zakończ naukę",
+                Msg::WindowFailed => "Nie można utworzyć okna programu: {}",
+                Msg::MenuDryRun => "Próba na sucho",
+                Msg::DryRunOn => "Próba na sucho: akcje są zapisywane w logu, nic nie jest wysyłane",
+                Msg::DryRunOff => "Koniec próby na sucho po {} akcjach, wysyłanie wznowione",
+                Msg::TipDryRun => "{} (próba na sucho)",
+                Msg::ScrollZoomNoHistory => "Brak wcześniejszej prędkości przewijania",
+                Msg::TrayPaused => "Wstrzymano, kliknij ikonę w zasobniku, aby wznowić",
+                Msg::NoOutputReports => "Urządzenie nie ma raportów wyjściowych",
+                Msg::OutputReportSize => "Raport wyjściowy o długości {} bajtów jest dłuższy niż {} urządzenia",
+                Msg::OutputReportFailed => "Nie można wysłać raportu wyjściowego: {}",
+                Msg::NoDevice => "Nie znaleziono urządzenia HID {}",
+                Msg::NoAssociation => "Żaden program nie jest ustawiony do otwierania tego rodzaju łączy",
+                Msg::DdeFailed => "Program nie odpowiedział na żądanie otwarcia",
+            },
+        }
+    }
+}
+
+/// Render `msg` in `lang`; surplus args are dropped, missing ones leave `{}` empty.
+pub fn text(lang: Language, msg: Msg, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut parts = msg.template(lang).split("{}");
+    if let Some(first) = parts.next() {
+        out.push_str(first);
+    }
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+/// Virtual key as written in the config, e.g. `"space"`, `"oem_4"` or `"f5"`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key(pub VIRTUAL_KEY);
+
+const NAMED: &[(&str, VIRTUAL_KEY)] = &[
+    ("space", VK_SPACE),
+    ("enter", VK_RETURN),
+    ("tab", VK_TAB),
+    ("escape", VK_ESCAPE),
+    ("backspace", VK_BACK),
+    ("delete", VK_DELETE),
+    ("insert", VK_INSERT),
+    ("home", VK_HOME),
+    ("end", VK_END),
+    ("page_up", VK_PRIOR),
+    ("page_down", VK_NEXT),
+    ("left", VK_LEFT),
+    ("right", VK_RIGHT),
+    ("up", VK_UP),
+    ("down", VK_DOWN),
+    ("oem_plus", VK_OEM_PLUS),
+    ("oem_minus", VK_OEM_MINUS),
+    ("oem_comma", VK_OEM_COMMA),
+    ("oem_period", VK_OEM_PERIOD),
+    ("oem_1", VK_OEM_1),
+    ("oem_2", VK_OEM_2),
+    ("oem_3", VK_OEM_3),
+    ("oem_4", VK_OEM_4),
+    ("oem_5", VK_OEM_5),
+    ("oem_6", VK_OEM_6),
+    ("oem_7", VK_OEM_7),
+    ("media_play_pause", VK_MEDIA_PLAY_PAUSE),
+    ("media_stop", VK_MEDIA_STOP),
+    ("media_next", VK_MEDIA_NEXT_TRACK),
+    ("media_prev", VK_MEDIA_PREV_TRACK),
+    ("volume_mute", VK_VOLUME_MUTE),
+    ("volume_up", VK_VOLUME_UP),
+    ("volume_down", VK_VOLUME_DOWN),
+];
+
+impl Key {
+    pub fn parse(name: &str) -> Option<Key> {
+        let name = name.to_ascii_lowercase();
+        if let Some((_, vk)) = NAMED.iter().find(|(n, _)| *n == name) {
+            return Some(Key(*vk));
+        }
+        if let Some(hex) = name.strip_prefix("0x") {
+            return u16::from_str_radix(hex, 16)
+                .ok()
+                .map(|vk| Key(VIRTUAL_KEY(vk)));
+        }
+        match name.as_bytes() {
+            // Letters and digits share their uppercase ASCII code
+            [c] if c.is_ascii_alphanumeric() => {
+                Some(Key(VIRTUAL_KEY(c.to_ascii_uppercase() as u16)))
+            }
+            [b'f', ..] => match name[1..].parse::<u16>() {
+                Ok(n @ 1..=24) => Some(Key(VIRTUAL_KEY(VK_F1.0 + n - 1))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vk = self.0 .0;
+        if let Some((name, _)) = NAMED.iter().find(|(_, k)| k.0 == vk) {
+            write!(f, "{}", name)
+        } else if (VK_F1.0..=VK_F24.0).contains(&vk) {
+            write!(f, "f{}", vk - VK_F1.0 + 1)
+        } else if (vk as u8 as u16 == vk) && (vk as u8).is_ascii_alphanumeric() {
+            write!(f, "{}", (vk as u8 as char).to_ascii_lowercase())
+        } else {
+            write!(f, "0x{:02x}", vk)
+        }
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(name: String) -> std::result::Result<Self, Self::Error> {
+        Key::parse(&name).ok_or_else(|| format!("unknown key name {:?}", name))
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> String {
+        key.to_string()
+    }
+}
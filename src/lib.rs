@@ -0,0 +1,557 @@
+//! Decoder for ShuttlePRO HID reports, free of any Win32 dependency so it
+//! can be reused outside the tray app, including from C through [`shuttle_decode`].
+
+use std::fmt;
+use std::slice;
+
+pub mod record;
+pub mod schema;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ContourHidEvent {
+    pub id: u8,
+    pub jog: i8,
+    pub wheel: u8,
+    pub _fill: u8,
+    pub keys: u16,
+}
+
+/// Size of a ShuttlePRO input report in bytes.
+pub const REPORT_SIZE: usize = 6;
+
+impl ContourHidEvent {
+    /// Whether this has the shape of a ShuttlePRO report: report id `id`
+    /// and the unused byte zero. Other consumer-control devices' reports
+    /// decoded as these would give phantom events.
+    pub fn has_signature(&self, id: u8) -> bool {
+        self.id == id && self._fill == 0
+    }
+
+    /// Decode a raw report; `None` if it is shorter than [`REPORT_SIZE`].
+    pub fn from_bytes(report: &[u8]) -> Option<Self> {
+        match *report {
+            [id, jog, wheel, fill, k0, k1, ..] => Some(ContourHidEvent {
+                id,
+                jog: jog as i8,
+                wheel,
+                _fill: fill,
+                keys: u16::from_le_bytes([k0, k1]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SystemState {
+    pub last: ContourHidEvent,
+    pub scroll_zoom: u8,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ContourEvents {
+    Jog(i8),
+    WheelLeft,
+    WheelRight,
+    ButtonUp(u16),
+    ButtonDown(u16),
+}
+
+/// `id` of `SystemState::last` before the first report, which no ShuttlePRO sends.
+const UNPRIMED: u8 = 0xFF;
+
+impl SystemState {
+    /// State before the first report; `id: 0xFF` marks it as unprimed.
+    pub const fn new() -> Self {
+        SystemState {
+            scroll_zoom: 0,
+            last: ContourHidEvent {
+                id: UNPRIMED,
+                jog: 0,
+                wheel: 0,
+                _fill: 0,
+                keys: 0,
+            },
+        }
+    }
+
+    /// Events between the last report and `new`, for a device with every
+    /// one of the 15 buttons a report can carry.
+    ///
+    /// The order is fixed: button presses, then the jog, then the wheel,
+    /// then button releases, buttons lowest first within each group. So a
+    /// button that is down in `new` is already down for the jog and wheel
+    /// events of the same report, and one released in it still counts for them.
+    ///
+    /// The first report after [`SystemState::new`] only primes the state: it
+    /// gives no jog or wheel events, as there is nothing to compare the ring
+    /// and wheel positions with, so a ring held off centre at (re)connect
+    /// doesn't jog. Buttons already down in it are pressed as usual.
+    pub fn update(&mut self, new: ContourHidEvent) -> Vec<ContourEvents> {
+        self.update_model(new, Model::ShuttleProV2.buttons())
+    }
+
+    /// Like [`SystemState::update`], ignoring key bits from `buttons` up,
+    /// which the model has no buttons for.
+    pub fn update_model(&mut self, new: ContourHidEvent, buttons: u16) -> Vec<ContourEvents> {
+        let buttons = diff_buttons(self.last.keys, new.keys, buttons.min(15) as u8);
+        let (mut evt, released): (Vec<_>, Vec<_>) = buttons
+            .into_iter()
+            .partition(|e| matches!(e, ContourEvents::ButtonDown(_)));
+        if self.last.id == UNPRIMED {
+            self.last = new;
+            evt.extend(released);
+            return evt;
+        }
+        if self.last.jog != new.jog {
+            evt.push(ContourEvents::Jog(new.jog));
+        }
+        if self.last.wheel != new.wheel {
+            let mut delta = new.wheel as i16 - self.last.wheel as i16;
+            if delta > 128 {
+                delta -= 256
+            }
+            if delta < -128 {
+                delta += 256
+            }
+            evt.push(if delta < 0 {
+                ContourEvents::WheelLeft
+            } else {
+                ContourEvents::WheelRight
+            });
+        }
+        evt.extend(released);
+
+        self.last = new;
+        evt
+    }
+}
+
+/// Press and release events turning key bits `old` into `new`, lowest
+/// button first, for the `count` buttons from bit 0 up; higher bits are ignored.
+pub fn diff_buttons(old: u16, new: u16, count: u8) -> Vec<ContourEvents> {
+    let changed = old ^ new;
+    (0..count.min(16) as u16)
+        .filter(|k| changed & (1 << k) != 0)
+        .map(|k| {
+            if new & (1 << k) != 0 {
+                ContourEvents::ButtonDown(k)
+            } else {
+                ContourEvents::ButtonUp(k)
+            }
+        })
+        .collect()
+}
+
+impl Default for SystemState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Contour Design's USB vendor id.
+pub const CONTOUR_VID: u16 = 0x0B33;
+/// ShuttlePRO generations, which differ in their product id and how many
+/// buttons they have.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Model {
+    ShuttleProV1,
+    ShuttleProV2,
+}
+
+impl Model {
+    pub const ALL: [Model; 2] = [Model::ShuttleProV1, Model::ShuttleProV2];
+
+    /// USB product id.
+    pub const fn pid(self) -> u16 {
+        match self {
+            Model::ShuttleProV1 => 0x0010,
+            Model::ShuttleProV2 => 0x0030,
+        }
+    }
+
+    /// Buttons 0..buttons() exist on this model.
+    pub const fn buttons(self) -> u16 {
+        match self {
+            Model::ShuttleProV1 => 13,
+            Model::ShuttleProV2 => 15,
+        }
+    }
+
+    /// Short name, as used in config.
+    pub const fn id(self) -> &'static str {
+        match self {
+            Model::ShuttleProV1 => "v1",
+            Model::ShuttleProV2 => "v2",
+        }
+    }
+
+    /// The model a device interface path belongs to, see [`is_device`].
+    pub fn from_name(name: &[u8]) -> Option<Model> {
+        Model::ALL
+            .into_iter()
+            .find(|m| is_device(name, CONTOUR_VID, m.pid()))
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Model::ShuttleProV1 => "ShuttlePRO v1",
+            Model::ShuttleProV2 => "ShuttlePRO v2",
+        })
+    }
+}
+
+/// Whether `name`, a raw input device interface path such as
+/// `\\?\HID#VID_0B33&PID_0030#7&...`, belongs to `vid`/`pid`. `name` may
+/// carry the terminating NUL; case is ignored, as Windows is not consistent
+/// about it.
+pub fn is_device(name: &[u8], vid: u16, pid: u16) -> bool {
+    let prefix = format!(r"\\?\hid#vid_{:04x}&pid_{:04x}", vid, pid);
+    let name = name.strip_suffix(&[0]).unwrap_or(name);
+    // The id ends at '#', or at '&' when an interface number (&mi_xx) follows
+    name.len() > prefix.len()
+        && name[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+        && matches!(name[prefix.len()], b'#' | b'&')
+}
+
+/// Largest jog deflection the ring reports either way.
+pub const JOG_MAX: u8 = 7;
+
+/// Shape a raw jog position: first clamp it to ±`clamp`, then rescale
+/// 1..=`clamp` onto 1..=`steps`, rounding away from centre so that only
+/// a centred ring maps to 0. Both limits are taken as 1..=[`JOG_MAX`].
+///
+/// E.g. `clamp = 4, steps = 4` treats every deflection from 4 up alike,
+/// `clamp = 7, steps = 3` compresses the range to -3..=3.
+pub fn scale_jog(raw: i8, clamp: u8, steps: u8) -> i8 {
+    let clamp = clamp.clamp(1, JOG_MAX) as i16;
+    let steps = steps.clamp(1, JOG_MAX) as i16;
+    let magnitude = (raw.unsigned_abs() as i16).min(clamp);
+    let scaled = (magnitude * steps + clamp - 1) / clamp;
+    (scaled * raw.signum() as i16) as i8
+}
+
+/// Count a wheel tick onto `ticks`, the ticks so far signed by direction,
+/// towards a step of `per_step` (taken as at least 1) in a row. Turning
+/// back starts over. Returns the new count, 0 once a step completes, and
+/// whether it did.
+pub fn count_tick(ticks: i32, right: bool, per_step: u16) -> (i32, bool) {
+    let sign = if right { 1 } else { -1 };
+    let ticks = if ticks.signum() == -sign {
+        sign
+    } else {
+        ticks + sign
+    };
+    if ticks.abs() >= per_step.max(1) as i32 {
+        (0, true)
+    } else {
+        (ticks, false)
+    }
+}
+
+/// The tier, distance from centre, a scaled jog entered moving from
+/// `previous` to `position`; `None` within a tier, so -3 to 3 is no edge.
+pub fn tier_edge(previous: i8, position: i8) -> Option<u8> {
+    let tier = position.unsigned_abs();
+    (tier != previous.unsigned_abs()).then_some(tier)
+}
+
+/// `ShuttleEvent::kind` values, one per [`ContourEvents`] variant.
+pub const SHUTTLE_EVENT_JOG: u32 = 0;
+pub const SHUTTLE_EVENT_WHEEL_LEFT: u32 = 1;
+pub const SHUTTLE_EVENT_WHEEL_RIGHT: u32 = 2;
+pub const SHUTTLE_EVENT_BUTTON_UP: u32 = 3;
+pub const SHUTTLE_EVENT_BUTTON_DOWN: u32 = 4;
+
+/// Most events a single report can produce: jog, wheel and every button.
+pub const SHUTTLE_MAX_EVENTS: usize = 2 + 15;
+
+/// C mirror of [`ContourEvents`]. `value` is the jog position for
+/// `SHUTTLE_EVENT_JOG`, the button index for button events and 0 otherwise.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ShuttleEvent {
+    pub kind: u32,
+    pub value: i32,
+}
+
+impl From<ContourEvents> for ShuttleEvent {
+    fn from(evt: ContourEvents) -> Self {
+        let (kind, value) = match evt {
+            ContourEvents::Jog(x) => (SHUTTLE_EVENT_JOG, x as i32),
+            ContourEvents::WheelLeft => (SHUTTLE_EVENT_WHEEL_LEFT, 0),
+            ContourEvents::WheelRight => (SHUTTLE_EVENT_WHEEL_RIGHT, 0),
+            ContourEvents::ButtonUp(b) => (SHUTTLE_EVENT_BUTTON_UP, b as i32),
+            ContourEvents::ButtonDown(b) => (SHUTTLE_EVENT_BUTTON_DOWN, b as i32),
+        };
+        ShuttleEvent { kind, value }
+    }
+}
+
+/// Back from the C mirror; fails for unknown kinds and out-of-range values.
+impl TryFrom<ShuttleEvent> for ContourEvents {
+    type Error = ShuttleEvent;
+
+    fn try_from(evt: ShuttleEvent) -> Result<Self, ShuttleEvent> {
+        let button = || u16::try_from(evt.value).map_err(|_| evt);
+        match evt.kind {
+            SHUTTLE_EVENT_JOG => i8::try_from(evt.value)
+                .map(ContourEvents::Jog)
+                .map_err(|_| evt),
+            SHUTTLE_EVENT_WHEEL_LEFT => Ok(ContourEvents::WheelLeft),
+            SHUTTLE_EVENT_WHEEL_RIGHT => Ok(ContourEvents::WheelRight),
+            SHUTTLE_EVENT_BUTTON_UP => button().map(ContourEvents::ButtonUp),
+            SHUTTLE_EVENT_BUTTON_DOWN => button().map(ContourEvents::ButtonDown),
+            _ => Err(evt),
+        }
+    }
+}
+
+/// Reset `state` to the unprimed state; call once before the first [`shuttle_decode`].
+///
+/// # Safety
+///
+/// `state` must point to writable memory for one `SystemState`, owned by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn shuttle_state_init(state: *mut SystemState) {
+    if !state.is_null() {
+        state.write(SystemState::new());
+    }
+}
+
+/// Feed one raw report through [`SystemState::update`].
+///
+/// Reads `len` bytes of `report`, updates `*state` in place and writes up to
+/// `capacity` events into `events`, in the order `update` documents.
+/// Returns the number of events the report produced, which is larger than
+/// `capacity` if some were dropped; a buffer of [`SHUTTLE_MAX_EVENTS`]
+/// never truncates. Returns -1, leaving `*state` untouched, if a pointer is
+/// null or `len` is below [`REPORT_SIZE`].
+///
+/// # Safety
+///
+/// All buffers stay owned by the caller and are only used for the duration
+/// of the call: `report` must be readable for `len` bytes, `state` must point
+/// to a `SystemState` initialized by [`shuttle_state_init`] and not used
+/// concurrently, and `events` must be writable for `capacity` entries.
+#[no_mangle]
+pub unsafe extern "C" fn shuttle_decode(
+    report: *const u8,
+    len: usize,
+    state: *mut SystemState,
+    events: *mut ShuttleEvent,
+    capacity: usize,
+) -> isize {
+    if report.is_null() || state.is_null() || (events.is_null() && capacity > 0) {
+        return -1;
+    }
+    let hid = match ContourHidEvent::from_bytes(slice::from_raw_parts(report, len)) {
+        Some(hid) => hid,
+        None => return -1,
+    };
+
+    let evts = (*state).update(hid);
+    for (i, evt) in evts.iter().take(capacity).enumerate() {
+        events.add(i).write((*evt).into());
+    }
+    evts.len() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(jog: i8, wheel: u8, keys: u16) -> ContourHidEvent {
+        ContourHidEvent {
+            id: 0,
+            jog,
+            wheel,
+            _fill: 0,
+            keys,
+        }
+    }
+
+    #[test]
+    fn first_report_only_primes() {
+        let mut state = SystemState::new();
+        assert_eq!(state.update(report(5, 200, 0)), []);
+        assert_eq!(state.update(report(6, 200, 0)), [ContourEvents::Jog(6)]);
+    }
+
+    #[test]
+    fn first_report_presses_held_buttons() {
+        let mut state = SystemState::new();
+        assert_eq!(
+            state.update(report(-3, 17, 0b10)),
+            [ContourEvents::ButtonDown(1)]
+        );
+    }
+
+    #[test]
+    fn diff_buttons_single_bit() {
+        assert_eq!(diff_buttons(0, 1 << 3, 15), [ContourEvents::ButtonDown(3)]);
+        assert_eq!(diff_buttons(1 << 3, 0, 15), [ContourEvents::ButtonUp(3)]);
+    }
+
+    #[test]
+    fn diff_buttons_multi_bit_lowest_first() {
+        assert_eq!(
+            diff_buttons(0b0101, 0b1010, 15),
+            [
+                ContourEvents::ButtonUp(0),
+                ContourEvents::ButtonDown(1),
+                ContourEvents::ButtonUp(2),
+                ContourEvents::ButtonDown(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_buttons_no_change() {
+        assert_eq!(diff_buttons(0b110, 0b110, 15), []);
+    }
+
+    #[test]
+    fn diff_buttons_ignores_bits_above_count() {
+        assert_eq!(
+            diff_buttons(0, 1 << 13 | 1 << 14 | 1, 13),
+            [ContourEvents::ButtonDown(0)]
+        );
+    }
+
+    #[test]
+    fn simultaneous_report_orders_events() {
+        let mut state = SystemState::new();
+        state.update(report(0, 10, 0b001));
+        assert_eq!(
+            state.update(report(2, 11, 0b110)),
+            [
+                ContourEvents::ButtonDown(1),
+                ContourEvents::ButtonDown(2),
+                ContourEvents::Jog(2),
+                ContourEvents::WheelRight,
+                ContourEvents::ButtonUp(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn scale_jog_full_range() {
+        let compressed: Vec<i8> = (-7..=7).map(|raw| scale_jog(raw, 7, 3)).collect();
+        assert_eq!(
+            compressed,
+            [-3, -3, -3, -2, -2, -1, -1, 0, 1, 1, 2, 2, 3, 3, 3]
+        );
+        let clamped: Vec<i8> = (-7..=7).map(|raw| scale_jog(raw, 4, 4)).collect();
+        assert_eq!(
+            clamped,
+            [-4, -4, -4, -4, -3, -2, -1, 0, 1, 2, 3, 4, 4, 4, 4]
+        );
+        for raw in -7..=7 {
+            assert_eq!(scale_jog(raw, 7, 7), raw);
+            assert_eq!(scale_jog(raw, 0, 0), raw.signum());
+        }
+    }
+
+    #[test]
+    fn is_device_matches_path_bytes() {
+        let pid = Model::ShuttleProV2.pid();
+        assert!(is_device(
+            br"\\?\HID#VID_0B33&PID_0030#7&1a2b&0&0000#{guid}",
+            CONTOUR_VID,
+            pid
+        ));
+        assert!(is_device(
+            br"\\?\hid#vid_0b33&pid_0030&mi_00#8&ab",
+            CONTOUR_VID,
+            pid
+        ));
+        assert!(is_device(
+            b"\\\\?\\HID#VID_0B33&PID_0030#x\0",
+            CONTOUR_VID,
+            pid
+        ));
+        assert!(!is_device(
+            br"\\?\HID#VID_0B33&PID_0010#7&1a2b",
+            CONTOUR_VID,
+            pid
+        ));
+        assert!(!is_device(
+            br"\\?\HID#VID_0B33&PID_00301#7",
+            CONTOUR_VID,
+            pid
+        ));
+        assert!(!is_device(br"\\?\HID#VID_0B33&PID_0030", CONTOUR_VID, pid));
+        assert!(!is_device(b"", CONTOUR_VID, pid));
+        assert_eq!(
+            Model::from_name(br"\\?\HID#VID_0B33&PID_0010#7&1a2b"),
+            Some(Model::ShuttleProV1)
+        );
+    }
+
+    #[test]
+    fn tier_edges_over_the_ring() {
+        let mut previous = scale_jog(-7, 7, 3);
+        let mut edges = Vec::new();
+        for raw in -7..=7 {
+            let position = scale_jog(raw, 7, 3);
+            if let Some(tier) = tier_edge(previous, position) {
+                edges.push((raw, tier));
+            }
+            previous = position;
+        }
+        assert_eq!(edges, [(-4, 2), (-2, 1), (0, 0), (1, 1), (3, 2), (5, 3)]);
+        assert_eq!(tier_edge(-3, 3), None);
+        assert_eq!(tier_edge(3, 0), Some(0));
+    }
+
+    /// Whether each of `right` completes a step of `per_step` ticks.
+    fn steps(per_step: u16, right: &[bool]) -> Vec<bool> {
+        let mut ticks = 0;
+        right
+            .iter()
+            .map(|right| {
+                let (next, done) = count_tick(ticks, *right, per_step);
+                ticks = next;
+                done
+            })
+            .collect()
+    }
+
+    #[test]
+    fn count_tick_divisors() {
+        let right = [true; 6];
+        assert_eq!(steps(0, &right), [true; 6]);
+        assert_eq!(steps(1, &right), [true; 6]);
+        assert_eq!(steps(2, &right), [false, true, false, true, false, true]);
+        assert_eq!(steps(3, &right), [false, false, true, false, false, true]);
+        // Turning back starts over
+        assert_eq!(
+            steps(2, &[true, false, false, true, true]),
+            [false, false, true, false, true]
+        );
+    }
+
+    #[test]
+    fn count_tick_across_wheel_wrap_around() {
+        let mut state = SystemState::new();
+        state.update(report(0, 253, 0));
+        let right: Vec<bool> = [254, 255, 0, 1]
+            .into_iter()
+            .flat_map(|wheel| state.update(report(0, wheel, 0)))
+            .map(|evt| evt == ContourEvents::WheelRight)
+            .collect();
+        assert_eq!(right, [true; 4]);
+        assert_eq!(steps(2, &right), [false, true, false, true]);
+        let left: Vec<bool> = [0, 255]
+            .into_iter()
+            .flat_map(|wheel| state.update(report(0, wheel, 0)))
+            .map(|evt| evt == ContourEvents::WheelRight)
+            .collect();
+        assert_eq!(left, [false; 2]);
+    }
+}
@@ -1,117 +1,254 @@
 #![windows_subsystem = "windows"]
 
+mod action;
+mod args;
+mod badge;
+mod config;
+mod console;
+mod diagnose;
+mod eventlog;
+mod hid;
+mod hidread;
+mod i18n;
+mod keys;
+#[cfg(feature = "net")]
+mod metrics;
+mod midi;
+#[cfg(feature = "net")]
+mod net;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod settings;
+#[cfg(feature = "net")]
+mod status;
+#[cfg(feature = "net")]
+mod udp;
+mod vjoy;
+
 use std::cmp::min;
+use std::ffi::CString;
 use std::mem;
+use std::path::PathBuf;
+use std::ptr;
+use std::time::{Duration, Instant};
 
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    VIRTUAL_KEY, VK_OEM_4, VK_OEM_6, VK_OEM_PLUS, VK_SPACE,
+    RegisterHotKey, UnregisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+    VIRTUAL_KEY, VK_CONTROL, VK_ESCAPE, VK_MEDIA_PLAY_PAUSE, VK_MENU, VK_OEM_MINUS, VK_OEM_PLUS,
+    VK_VOLUME_DOWN, VK_VOLUME_MUTE, VK_VOLUME_UP,
 };
 use windows::Win32::UI::Input::{
     GetRawInputData, GetRawInputDeviceInfoA, RegisterRawInputDevices, HRAWINPUT, RAWINPUT,
     RAWINPUTDEVICE, RAWINPUTHEADER, RIDEV_DEVNOTIFY, RIDEV_INPUTSINK, RIDI_DEVICENAME, RID_INPUT,
 };
 use windows::Win32::UI::Shell::{
-    Shell_NotifyIconA, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NOTIFYICONDATAA,
+    Shell_NotifyIconA, Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_MODIFY,
+    NOTIFYICONDATAA, NOTIFYICONDATAW,
 };
 use windows::{
     core::*, Win32::Foundation::*, Win32::Graphics::Gdi::ValidateRect,
     Win32::System::LibraryLoader::GetModuleHandleA, Win32::UI::WindowsAndMessaging::*,
 };
-use winrt_notification::{Duration, Sound, Toast};
+use winrt_notification::Toast;
+
+use shuttle_pro_rs::record::{Format, Recorder};
+#[cfg(feature = "net")]
+use shuttle_pro_rs::schema;
+use shuttle_pro_rs::{
+    count_tick, scale_jog, tier_edge, ContourEvents, ContourHidEvent, Model, SystemState,
+    REPORT_SIZE,
+};
+
+use action::{Action, Modifier};
+use config::{
+    Binding, Category, ChordInput, ChordStep, ChordWait, Config, Decode, InputMode, JogMode,
+    NoTarget, ScrollAxis, ToastStyle, TrayClick, Unmapped, WheelAction, WheelTarget, ZoomWith,
+};
+use eventlog::Level;
+use i18n::{Language, Msg};
 
 const APPWM_ICONNOTIFY: u32 = WM_APP + 1;
+/// Posted by the `--pick-window` hook, wparam being the window clicked, 0 if cancelled.
+const APPWM_PICKED: u32 = APPWM_ICONNOTIFY + 3;
+
+// WM_INPUT_DEVICE_CHANGE wparam, from winuser.h
+const GIDC_REMOVAL: u32 = 2;
+// Key state in mouse message wparams, from winuser.h
+const MK_CONTROL: u32 = 0x0008;
+
+// WM_DEVICECHANGE events, from dbt.h
+const DBT_DEVNODES_CHANGED: u32 = 0x0007;
+const DBT_DEVICEARRIVAL: u32 = 0x8000;
+
+// Tray menu commands
+const MENU_SETTINGS: usize = 100;
+const MENU_RESET: usize = 101;
+const MENU_QUIT: usize = 102;
+const MENU_LAST_EVENT: usize = 103;
+const MENU_RELOAD: usize = 104;
+const MENU_STOP_LEARNING: usize = 105;
+const MENU_DRY_RUN: usize = 106;
+
+/// `RegisterHotKey` id of `reload_hotkey`.
+const HOTKEY_RELOAD: i32 = 1;
+/// `RegisterHotKey` id of Esc while learning.
+const HOTKEY_LEARN: i32 = 2;
+
+/// Timer collapsing a burst of device changes into one re-registration.
+const TIMER_REREGISTER: usize = 1;
+const TIMER_CHORD: usize = 2;
+const TIMER_TARGET_POLL: usize = 3;
+const TIMER_IDLE: usize = 4;
+/// End of the `--diagnose` capture.
+const TIMER_DIAGNOSE: usize = 5;
+/// End of `startup_delay_ms`.
+const TIMER_STARTUP: usize = 6;
+/// Scrolling while the jog is held out, with `jog.mode = "scroll"`.
+const TIMER_JOG_SCROLL: usize = 7;
+/// `TIMER_REPEAT + b` repeats button `b` while it is held.
+const TIMER_REPEAT: usize = 0x100;
+const REREGISTER_DELAY_MS: u32 = 1000;
 
 union RawInputWrapper {
     ri: RAWINPUT,
     _data: [u8; 1024],
 }
 
-#[repr(C)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-struct ContourHidEvent {
-    id: u8,
-    jog: i8,
-    wheel: u8,
-    _fill: u8,
-    keys: u16,
+enum Scroll {
+    Left(u16),
+    Right(u16),
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-struct SystemState {
-    last: ContourHidEvent,
-    scroll_zoom: u8,
+fn main() {
+    match xmain() {
+        Ok(()) => {}
+        Err(msg) => toast(Category::Error, msg.to_string().as_str()),
+    }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum ContourEvents {
-    Jog(i8),
-    WheelLeft,
-    WheelRight,
-    ButtonUp(u16),
-    ButtonDown(u16),
-}
+fn xmain() -> Result<()> {
+    let args = args::parse()?;
+    if args.console {
+        console::open();
+    }
+    if args.init {
+        let path = config::init(args.force)?;
+        toast(
+            Category::Action,
+            &tr(Msg::ConfigWritten, &[&path.display()]),
+        );
+        return Ok(());
+    }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum Scroll {
-    Left(u8),
-    Right(u8),
-}
+    let (paths, loaded) = if args.config.is_empty() {
+        let path = config::locate()?;
+        let config = config::load(&path)?;
+        (vec![path], config)
+    } else {
+        let config = config::load_layers(&args.config)?;
+        (args.config.clone(), config)
+    };
+    unsafe {
+        ACTIVE_PROFILE = loaded
+            .profile
+            .as_deref()
+            .and_then(|p| loaded.profile_index(p));
+        CONFIG = Some(loaded);
+        CONFIG_PATHS = paths;
+    }
 
-impl SystemState {
-    fn update(&mut self, new: ContourHidEvent) -> Vec<ContourEvents> {
-        let mut evt = Vec::new();
-        if self.last.id != 0 {
-            self.last.wheel = new.wheel;
-        }
+    if args.dump {
+        return dump_config(args.dump_path.as_deref());
+    }
+    if args.selftest {
+        return selftest();
+    }
 
-        if self.last.jog != new.jog {
-            evt.push(ContourEvents::Jog(new.jog));
+    let conflicts = config().conflicts();
+    if !conflicts.is_empty() {
+        for c in &conflicts {
+            println!("Config warning: {}", c);
         }
-        if self.last.wheel != new.wheel {
-            let mut delta = new.wheel as i16 - self.last.wheel as i16;
-            if delta > 128 {
-                delta -= 256
-            }
-            if delta < -128 {
-                delta += 256
+        toast(
+            Category::Error,
+            &tr(Msg::ConfigWarnings, &[&conflicts.join("\n")]),
+        );
+    }
+
+    if let Some(file) = &args.record {
+        let format = args.format.unwrap_or_else(|| Format::from_path(file));
+        match Recorder::create(file, format) {
+            Ok(recorder) => {
+                println!("Recording to {} as {:?}", file.display(), format);
+                unsafe { RECORDER = Some(recorder) };
             }
-            evt.push(if delta < 0 {
-                ContourEvents::WheelLeft
-            } else {
-                ContourEvents::WheelRight
-            });
+            Err(err) => toast(
+                Category::Error,
+                &tr(Msg::RecordFailed, &[&file.display(), &err]),
+            ),
         }
-        if self.last.keys != new.keys {
-            for k in 0u16..15 {
-                let last_key = self.last.keys & (1 << k) != 0;
-                let new_key = new.keys & (1 << k) != 0;
-                match (last_key, new_key) {
-                    (false, true) => {
-                        evt.push(ContourEvents::ButtonDown(k));
-                    }
-                    (true, false) => {
-                        evt.push(ContourEvents::ButtonUp(k));
-                    }
-                    _ => (),
-                }
-            }
+    }
+
+    if config().midi.enabled {
+        if let Err(err) = midi::open(config().midi.device) {
+            toast(Category::Error, &err.to_string());
         }
+    }
 
-        self.last = new;
-        evt
+    if config().vjoy.enabled {
+        if let Err(err) = vjoy::open(&config().vjoy) {
+            toast(Category::Error, &err.to_string());
+        }
     }
-}
 
-const CONTOUR_ID: &str = r#"\\?\hid#vid_0b33&pid_0030#"#;
+    #[cfg(feature = "net")]
+    match net::start() {
+        Ok(()) => {
+            if config().udp.enabled {
+                let udp = &config().udp;
+                udp::start(udp.address.clone(), udp.format, udp.heartbeat_ms);
+            }
+            if config().metrics.enabled {
+                metrics::start(config().metrics.address.clone());
+            }
+            if config().status.enabled {
+                status::start(config().status.address.clone());
+            }
+        }
+        Err(err) => toast(Category::Error, &err.to_string()),
+    }
+    #[cfg(not(feature = "net"))]
+    for (enabled, name) in [
+        (config().udp.enabled, "UDP"),
+        (config().metrics.enabled, "Metrics"),
+        (config().status.enabled, "Status"),
+    ] {
+        if enabled {
+            toast(Category::Error, &tr(Msg::BackendDisabled, &[&name, &"net"]));
+        }
+    }
 
-fn main() {
-    match xmain() {
-        Ok(()) => {}
-        Err(msg) => message("Error", msg.to_string().as_str()),
+    #[cfg(feature = "scripting")]
+    if let Some(script) = &config().script.path {
+        // Relative to the config file's directory
+        let script = config_path().with_file_name(script);
+        if let Err(err) = scripting::load(&script, config().script.timeout_ms) {
+            toast(Category::Error, &tr(Msg::ScriptFailed, &[&err]));
+        }
+    }
+    #[cfg(not(feature = "scripting"))]
+    if config().script.path.is_some() {
+        toast(
+            Category::Error,
+            &tr(Msg::BackendDisabled, &[&"Scripting", &"scripting"]),
+        );
     }
-}
 
-fn xmain() -> Result<()> {
     let instance = unsafe { GetModuleHandleA(None) }?;
     debug_assert!(instance.0 != 0);
 
@@ -146,37 +283,615 @@ fn xmain() -> Result<()> {
             None,
         )
     };
+    if wnd.0 == 0 {
+        // Read right away, before another call can overwrite the last error
+        let err = Error::from_win32();
+        println!(
+            "CreateWindowExA failed: {} (GetLastError {})",
+            err,
+            err.code().0 & 0xFFFF
+        );
+        return Err(app_error(&tr(Msg::WindowFailed, &[&err])));
+    }
+
+    unsafe { MAIN_WND = wnd };
+    register_hotkey();
+    register_icon(wnd);
+    update_icon();
+    eventlog::enable(config().event_log);
+    eventlog::write(
+        Level::Info,
+        &format!("Started, config {}", config_path().display()),
+    );
+
+    if let Some(secs) = args.diagnose {
+        diagnose::start();
+        unsafe { SetTimer(wnd, TIMER_DIAGNOSE, secs.saturating_mul(1000), None) };
+        toast(Category::Action, &tr(Msg::DiagnoseStarted, &[&secs]));
+    }
+
+    if config().target.remember_pick {
+        load_pick();
+    }
+    if args.pick_window {
+        start_pick(instance);
+    }
+    if args.learn {
+        start_learning(wnd);
+    }
+    if args.dry_run {
+        set_dry_run(true);
+    }
+
+    // Reset the decoder before the first WM_INPUT can be queued. Its first
+    // report only primes it: buttons held then are pressed, but the jog and
+    // wheel positions become the baseline, so a ring held off centre at
+    // launch does nothing until it moves.
+    unsafe { GLOBAL_STATE = SystemState::new() };
+
+    match config().startup_delay_ms {
+        // Without input there is nothing to do, exit through the error toast
+        0 => start_input(wnd)?,
+        ms => {
+            println!("Waiting {} ms before starting", ms);
+            unsafe { SetTimer(wnd, TIMER_STARTUP, ms, None) };
+        }
+    }
+
+    let hook = watch_foreground();
+
+    let mut message = MSG::default();
+
+    while unsafe { GetMessageA(&mut message, None, 0, 0) }.into() {
+        // Tab and Enter handling for the settings window
+        let settings = settings::window();
+        if settings.0 != 0 && unsafe { IsDialogMessageW(settings, &message) }.as_bool() {
+            continue;
+        }
+        unsafe {
+            TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+    }
+
+    if let Some(hook) = hook {
+        unsafe { UnhookWinEvent(hook) };
+    }
+    eventlog::write(Level::Info, "Stopped");
+    eventlog::enable(false);
+    vjoy::close();
+    console::close();
+    Ok(())
+}
+
+/// The foreground window as of the last focus change, once the hook is in.
+static mut FOREGROUND_WND: Option<HWND> = None;
+
+/// Follow focus changes with a WinEvent hook rather than asking for the
+/// foreground window on every event; `None` if it couldn't be installed,
+/// in which case every lookup asks.
+fn watch_foreground() -> Option<HWINEVENTHOOK> {
+    // Out of context, the callback runs on this thread while it waits for
+    // messages, so it may use the same state as the window procedure
+    let hook = unsafe {
+        SetWinEventHook(
+            EVENT_SYSTEM_FOREGROUND,
+            EVENT_SYSTEM_FOREGROUND,
+            None,
+            Some(foreground_changed),
+            0,
+            0,
+            WINEVENT_OUTOFCONTEXT,
+        )
+    };
+    if hook.0 == 0 {
+        println!("Unable to watch the foreground window, checking it per event");
+        return None;
+    }
+    unsafe { FOREGROUND_WND = Some(GetForegroundWindow()) };
+    Some(hook)
+}
+
+unsafe extern "system" fn foreground_changed(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    window: HWND,
+    _object: i32,
+    _child: i32,
+    _thread: u32,
+    _time: u32,
+) {
+    FOREGROUND_WND = Some(window);
+    if config().auto_profile {
+        auto_profile(window);
+    }
+    // A window that just opened and took focus should count right away
+    refresh_candidates();
+    foreground_ok();
+}
+
+/// Switch to the profile whose `target` matches `foreground`, picking one
+/// by `profile_tiebreak` if several do. Without a match the profile stays,
+/// so focusing the desktop or a dialog doesn't flip it back and forth.
+fn auto_profile(foreground: HWND) {
+    let wnd = unsafe { GetAncestor(foreground, GA_ROOT) };
+    if wnd.0 == 0 {
+        return;
+    }
+    let class = window_class(wnd);
+    let matching: Vec<usize> = config()
+        .profiles
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| {
+            p.target
+                .as_ref()
+                .is_some_and(|m| (m.class.is_empty() || m.class == class) && window_matches(wnd, m))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let Some(chosen) = config().resolve_profile(&matching) else {
+        return;
+    };
+    let name = &config().profiles[chosen].name;
+    if matching.len() > 1 {
+        let names: Vec<&str> = matching
+            .iter()
+            .map(|i| config().profiles[*i].name.as_str())
+            .collect();
+        println!(
+            "Profiles {:?} all match, {:?} picks {}",
+            names,
+            config().profile_tiebreak,
+            name
+        );
+    }
+    if unsafe { ACTIVE_PROFILE } != Some(chosen) {
+        unsafe { ACTIVE_PROFILE = Some(chosen) };
+        update_icon();
+        println!("Profile {} for the foreground window", name);
+        toast(Category::Profile, &tr(Msg::Profile, &[name]));
+    }
+}
+
+fn foreground_window() -> HWND {
+    unsafe { FOREGROUND_WND }.unwrap_or_else(|| unsafe { GetForegroundWindow() })
+}
+
+/// The effective config as text, with where it came from and the active profile.
+fn dump_text() -> String {
+    let profile = unsafe { ACTIVE_PROFILE }.map(|i| config().profiles[i].name.as_str());
+    config::dump(config(), unsafe { global(&raw mut CONFIG_PATHS) }, profile)
+}
+
+fn dump_config(out: Option<&std::path::Path>) -> Result<()> {
+    let text = dump_text();
+    match out {
+        Some(out) => {
+            std::fs::write(out, text)
+                .map_err(|e| app_error(&format!("{}: {}", out.display(), e)))?;
+            toast(Category::Action, &tr(Msg::ConfigDumped, &[&out.display()]));
+        }
+        None => {
+            let text = CString::new(text).unwrap_or_default();
+            unsafe {
+                MessageBoxA(
+                    None,
+                    PCSTR(text.as_ptr() as *const u8),
+                    s!("Effective config"),
+                    MB_OK | MB_ICONINFORMATION,
+                )
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Run reports for a jog and wheel turn each way and every key-sending
+/// button, other than chord and repeat ones, through
+/// `process_contour_event`, as if from the device, and show what got
+/// posted to the target for each.
+fn selftest() -> Result<()> {
+    let name = &config().target.name;
+    if find_candidates().is_empty() {
+        return Err(app_error(&tr(Msg::SelftestNoTarget, &[name])));
+    }
+
+    let report = |jog: i8, wheel: u8, keys: u16| {
+        let [k0, k1] = keys.to_le_bytes();
+        [0, jog as u8, wheel, 0, k0, k1]
+    };
+    let mut steps = vec![
+        ("jog +1".to_string(), vec![report(1, 0, 0), report(0, 0, 0)]),
+        (
+            "jog -1".to_string(),
+            vec![report(-1, 0, 0), report(0, 0, 0)],
+        ),
+        ("wheel +1".to_string(), vec![report(0, 1, 0)]),
+        ("wheel -1".to_string(), vec![report(0, 0, 0)]),
+    ];
+    for b in 0..unsafe { MODEL }.buttons() {
+        // Only bindings that post to the target; the rest could run programs.
+        // Chord and repeat buttons need timers on a window, so they're left out
+        let Some(binding) = config().button(b) else {
+            continue;
+        };
+        if binding.repeat_while_held || config().in_chord(b) {
+            continue;
+        }
+        if let Action::Key { .. } | Action::Sequence { .. } = binding.action {
+            steps.push((
+                format!("button {}", b),
+                vec![report(0, 0, 1 << b), report(0, 0, 0)],
+            ));
+        }
+    }
+
+    // No window is needed with chords and repeats left out
+    unsafe { GLOBAL_STATE = SystemState::new() };
+    process_contour_event(HWND(0), &report(0, 0, 0));
+    let mut lines = Vec::new();
+    let mut failed = false;
+    for (label, reports) in steps {
+        unsafe { POSTED = (0, 0) };
+        for r in reports {
+            process_contour_event(HWND(0), &r);
+        }
+        let (ok, lost) = unsafe { POSTED };
+        let msg = match (ok, lost) {
+            (0, 0) => Msg::SelftestNothing,
+            (_, 0) => Msg::SelftestPass,
+            _ => Msg::SelftestFail,
+        };
+        failed |= msg != Msg::SelftestPass;
+        lines.push(tr(msg, &[&label, &ok, &lost]));
+    }
+
+    let text = tr(Msg::SelftestResult, &[name, &lines.join("\n")]);
+    let style = if failed {
+        MB_ICONWARNING
+    } else {
+        MB_ICONINFORMATION
+    };
+    let title = tr(Msg::SelftestTitle, &[]);
+    unsafe {
+        MessageBoxW(
+            None,
+            &HSTRING::from(text),
+            &HSTRING::from(title),
+            MB_OK | style,
+        )
+    };
+    Ok(())
+}
+
+/// Look for the target and start taking device input.
+fn start_input(wnd: HWND) -> Result<()> {
+    start_target_poll();
+    match config().input_mode {
+        InputMode::RawInput => register_devices(wnd),
+        InputMode::ReadFile => hidread::start(wnd).map_err(|e| app_error(&e.to_string())),
+    }
+    .map_err(|err| app_error(&tr(Msg::RegisterFailed, &[&err])))
+}
+
+/// Write the `--diagnose` report, toast where it went and quit.
+fn finish_diagnose() {
+    let path = diagnose::report_path();
+    let sections = [
+        diagnose_build(),
+        diagnose_device(),
+        diagnose_target(),
+        dump_text(),
+    ];
+    match diagnose::finish(&path, sections) {
+        Ok(()) => toast(
+            Category::Action,
+            &tr(Msg::DiagnoseWritten, &[&path.display()]),
+        ),
+        Err(err) => toast(Category::Error, &format!("{}: {}", path.display(), err)),
+    }
+    unsafe { PostQuitMessage(0) };
+}
+
+fn diagnose_build() -> String {
+    let features: Vec<&str> = [
+        ("midi", cfg!(feature = "midi")),
+        ("net", cfg!(feature = "net")),
+        ("scripting", cfg!(feature = "scripting")),
+        ("vjoy", cfg!(feature = "vjoy")),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| on.then_some(name))
+    .collect();
+    format!(
+        "version {}\nfeatures: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        if features.is_empty() {
+            "(none)".to_string()
+        } else {
+            features.join(", ")
+        }
+    )
+}
+
+fn diagnose_device() -> String {
+    let dev = unsafe { CONTOUR_DEVICE };
+    if dev.0 == 0 {
+        return "no ShuttlePRO input seen\n".to_string();
+    }
+    let name = device_name(dev).map_or_else(String::new, |n| {
+        String::from_utf8_lossy(&n)
+            .trim_end_matches('\0')
+            .to_string()
+    });
+    format!(
+        "model: {} ({} buttons)\nhandle: {:#X}\npath: {}\n",
+        unsafe { MODEL },
+        unsafe { MODEL }.buttons(),
+        dev.0,
+        name
+    )
+}
+
+fn diagnose_target() -> String {
+    let candidates = refresh_candidates();
+    let chosen = select_target(&candidates);
+    let mut out = format!(
+        "match: {:?}\nbroadcast: {}\nforeground: {:#X}\n",
+        config().matcher(unsafe { ACTIVE_PROFILE }),
+        config().target.broadcast,
+        unsafe { GetForegroundWindow() }.0
+    );
+    if candidates.is_empty() {
+        out.push_str("no matching window\n");
+    }
+    for wnd in candidates {
+        let mark = if Some(wnd) == chosen {
+            " (selected)"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "window {:#X}{}: {} ({})\n",
+            wnd.0,
+            mark,
+            window_title(wnd),
+            window_process(wnd).unwrap_or_default()
+        ));
+    }
+    out
+}
 
+/// Ask for raw input from the `[device]` collection to be delivered to `wnd`, even in background.
+fn register_devices(wnd: HWND) -> Result<()> {
+    let device = &config().device;
     let devices: [RAWINPUTDEVICE; 1] = [RAWINPUTDEVICE {
-        usUsagePage: 0x000C,
-        usUsage: 0x0001,
+        usUsagePage: device.usage_page,
+        usUsage: device.usage,
         dwFlags: RIDEV_INPUTSINK | RIDEV_DEVNOTIFY,
         hwndTarget: wnd,
     }];
 
-    unsafe { RegisterRawInputDevices(&devices, mem::size_of_val(&devices) as u32) };
+    let ok = unsafe { RegisterRawInputDevices(&devices, mem::size_of::<RAWINPUTDEVICE>() as u32) };
+    if !ok.as_bool() {
+        let code = unsafe { GetLastError() };
+        println!("RegisterRawInputDevices failed, GetLastError = {}", code.0);
+        return Err(code.to_hresult().into());
+    }
 
-    let mut message = MSG::default();
+    println!(
+        "RegisterRawInputDevices ok: usage {:04X}:{:04X} -> {:?}",
+        devices[0].usUsagePage, devices[0].usUsage, wnd
+    );
+    Ok(())
+}
 
-    register_icon(wnd);
+/// Borrow one of the `static mut` globals. Only the window thread touches
+/// them, and a borrow must end before anything else borrows that global.
+unsafe fn global<T>(global: *mut T) -> &'static mut T {
+    &mut *global
+}
 
-    while unsafe { GetMessageA(&mut message, None, 0, 0) }.into() {
-        unsafe { DispatchMessageA(&message) };
+/// The running config; only ever replaced by one that validated.
+static mut CONFIG: Option<Config> = None;
+
+fn config() -> &'static Config {
+    unsafe { global(&raw mut CONFIG).as_ref() }.expect("config not loaded")
+}
+
+/// The config files in effect, lowest layer first: the located one, or those
+/// given with `--config`.
+static mut CONFIG_PATHS: Vec<PathBuf> = Vec::new();
+
+/// The top config layer, where the settings window and reset write.
+fn config_path() -> PathBuf {
+    unsafe { global(&raw mut CONFIG_PATHS).last().cloned() }.expect("config not loaded")
+}
+
+/// Switch to an already validated config, staying in the active profile if it still exists.
+fn apply_config(new: Config) {
+    let old = unsafe { ptr::replace(&raw mut CONFIG, Some(new)) };
+    config_swapped(old.as_ref());
+}
+
+/// Follow up on the running config replacing `old`: stay in the active
+/// profile if it still exists, and restart what depends on the config.
+fn config_swapped(old: Option<&Config>) {
+    let active = unsafe { ACTIVE_PROFILE }
+        .and_then(|i| old?.profiles.get(i))
+        .map(|p| p.name.clone());
+    let profile = active.or_else(|| config().profile.clone());
+    unsafe {
+        ACTIVE_PROFILE = profile.and_then(|p| config().profile_index(&p));
+        global(&raw mut TARGET_CACHE).clear();
+    }
+    update_icon();
+    start_target_poll();
+    eventlog::enable(config().event_log);
+    register_hotkey();
+    println!("Config applied");
+    eventlog::write(Level::Info, "Config applied");
+}
+
+/// Load the config files again and switch to them once they have loaded
+/// and validated in full. If they don't, the running config, the last good
+/// one, is kept and the error reported; false then.
+fn reload_config() -> bool {
+    let running = unsafe { global(&raw mut CONFIG) }
+        .as_mut()
+        .expect("config not loaded");
+    match config::reload(running, unsafe { global(&raw mut CONFIG_PATHS) }) {
+        Ok(old) => {
+            config_swapped(Some(&old));
+            true
+        }
+        Err(err) => {
+            println!("Reload failed, keeping the last good config: {}", err);
+            toast(Category::Error, &tr(Msg::ReloadFailed, &[&err]));
+            false
+        }
     }
+}
 
-    Ok(())
+/// Reload on request, from the tray menu or the hotkey, toasting the result.
+fn reload_now() {
+    println!("Reloading config");
+    if reload_config() {
+        toast(Category::Action, &tr(Msg::ConfigReloaded, &[]));
+    }
 }
 
-static mut GLOBAL_STATE: SystemState = SystemState {
-    scroll_zoom: 0,
-    last: ContourHidEvent {
-        id: 0xFF,
-        jog: 0,
-        wheel: 0,
-        _fill: 0,
-        keys: 0,
-    },
-};
+/// (Re)register `reload_hotkey` for the main window, once it exists.
+fn register_hotkey() {
+    let window = unsafe { MAIN_WND };
+    if window.0 == 0 {
+        return;
+    }
+    unsafe { UnregisterHotKey(window, HOTKEY_RELOAD) };
+    let Some(hotkey) = &config().reload_hotkey else {
+        return;
+    };
+    let modifiers = hotkey.modifiers.iter().fold(MOD_NOREPEAT, |all, m| {
+        all | match m {
+            Modifier::Ctrl => MOD_CONTROL,
+            Modifier::Shift => MOD_SHIFT,
+            Modifier::Alt => MOD_ALT,
+            Modifier::Win => MOD_WIN,
+        }
+    });
+    let ok = unsafe { RegisterHotKey(window, HOTKEY_RELOAD, modifiers, hotkey.key.0 .0 as u32) };
+    if !ok.as_bool() {
+        println!("RegisterHotKey failed: {}", Error::from_win32());
+        toast(Category::Error, &tr(Msg::HotkeyFailed, &[]));
+    }
+}
+
+/// Index into `config().profiles`, `None` while no profile is active.
+static mut ACTIVE_PROFILE: Option<usize> = None;
+
+fn language() -> Language {
+    unsafe { global(&raw mut CONFIG).as_ref() }
+        .map(|c| c.language)
+        .unwrap_or_default()
+}
+
+/// `msg` from the catalog in the configured language.
+fn tr(msg: Msg, args: &[&dyn std::fmt::Display]) -> String {
+    i18n::text(language(), msg, args)
+}
+
+fn app_error(text: &str) -> Error {
+    Error::new(E_FAIL, text.into())
+}
+
+static mut GLOBAL_STATE: SystemState = SystemState::new();
+
+/// Set after `idle_pause_ms` without input; the next report clears it.
+static mut PAUSED: bool = false;
+/// Buttons whose press came in a report dropped by `idle_drop_wake`, so
+/// their release is dropped too.
+static mut WAKE_KEYS: u16 = 0;
+
+/// `--record` target.
+static mut RECORDER: Option<Recorder> = None;
+
+/// Jog position after `scale_jog`, to skip raw changes that scale to the same value.
+static mut JOG_POSITION: i8 = 0;
+
+/// `Modifier::bit`s added to the next key action only.
+static mut STICKY_PENDING: u8 = 0;
+/// `Modifier::bit`s added to every key action until unlocked.
+static mut STICKY_LOCKED: u8 = 0;
+
+/// Modifiers for a key action about to be sent, using up the one-shot ones.
+fn take_sticky() -> Vec<VIRTUAL_KEY> {
+    let bits = unsafe { ptr::replace(&raw mut STICKY_PENDING, 0) | STICKY_LOCKED };
+    Modifier::ALL
+        .into_iter()
+        .filter(|m| bits & m.bit() != 0)
+        .map(Modifier::key)
+        .collect()
+}
+
+/// Bit `b` is set while button `b` is held with `repeat_while_held` running.
+static mut REPEATING: u16 = 0;
+
+/// When each repeating button was pressed, for `max_hold_ms` and the ramp.
+static mut HOLD_START: [Option<Instant>; config::BUTTON_COUNT as usize] =
+    [None; config::BUTTON_COUNT as usize];
+/// Current repeat interval of each repeating button.
+static mut REPEAT_INTERVAL: [u32; config::BUTTON_COUNT as usize] =
+    [0; config::BUTTON_COUNT as usize];
+
+fn start_repeat(window: HWND, button: u16, binding: &Binding) {
+    unsafe {
+        REPEATING |= 1 << button;
+        HOLD_START[button as usize] = Some(Instant::now());
+        REPEAT_INTERVAL[button as usize] = binding.repeat_ms();
+        SetTimer(
+            window,
+            TIMER_REPEAT + button as usize,
+            binding.repeat_ms(),
+            None,
+        );
+    }
+}
+
+/// Move a held button's repeat timer along its acceleration ramp.
+fn ramp_repeat(window: HWND, button: u16, binding: &Binding) {
+    let Some(start) = (unsafe { HOLD_START[button as usize] }) else {
+        return;
+    };
+    let interval = binding.repeat_interval(start.elapsed());
+    if interval != unsafe { mem::replace(&mut REPEAT_INTERVAL[button as usize], interval) } {
+        // Setting an existing timer again replaces its interval
+        unsafe { SetTimer(window, TIMER_REPEAT + button as usize, interval, None) };
+    }
+}
+
+fn stop_repeat(window: HWND, button: u16) {
+    unsafe {
+        KillTimer(window, TIMER_REPEAT + button as usize);
+        REPEATING &= !(1 << button);
+        HOLD_START[button as usize] = None;
+    }
+}
+
+/// Whether `button` has been held past `max_hold_ms`.
+fn held_too_long(button: u16) -> bool {
+    let max = config().max_hold_ms;
+    let start = unsafe { HOLD_START[button as usize] };
+    max != 0 && start.is_some_and(|t| t.elapsed() > Duration::from_millis(max as u64))
+}
+
+fn stop_all_repeats(window: HWND) {
+    for b in 0..config::BUTTON_COUNT {
+        if unsafe { REPEATING } & (1 << b) != 0 {
+            stop_repeat(window, b);
+        }
+    }
+}
 
 extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     match message {
@@ -194,7 +909,16 @@ extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: L
         APPWM_ICONNOTIFY => match lparam.0 as u32 {
             WM_LBUTTONUP => {
                 println!("WM_NOTIFY WM_LBUTTONUP");
-                unsafe { PostQuitMessage(0) };
+                match config().tray_click {
+                    TrayClick::Settings => open_settings(),
+                    TrayClick::Pause => toggle_pause(window),
+                    TrayClick::Quit => unsafe { PostQuitMessage(0) },
+                }
+                LRESULT(0)
+            }
+
+            WM_RBUTTONUP => {
+                show_tray_menu(window);
                 LRESULT(0)
             }
 
@@ -204,135 +928,1834 @@ extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: L
             }
         },
 
-        WM_INPUT => {
-            //  println!("WM_INPUT");
-            let mut data: RawInputWrapper = unsafe { mem::zeroed() };
-            let mut size = mem::size_of::<RawInputWrapper>() as u32;
-            let rc = unsafe {
-                GetRawInputData(
-                    HRAWINPUT(lparam.0),
-                    RID_INPUT,
-                    Some(&mut data as *mut RawInputWrapper as *mut ::core::ffi::c_void),
-                    &mut size,
-                    mem::size_of::<RAWINPUTHEADER>() as u32,
-                )
-            };
-            if rc < 1 {
-                return LRESULT(0);
-            }
-            let dev = unsafe { data.ri.header.hDevice };
-            let mut name = [0u8; 1024];
-            let mut dlen = 1024u32;
-            let rc = unsafe {
-                GetRawInputDeviceInfoA(
-                    dev,
-                    RIDI_DEVICENAME,
-                    Some(name.as_mut_ptr() as *mut ::core::ffi::c_void),
-                    &mut dlen,
-                )
-            };
-            if rc < 1 {
-                return LRESULT(0);
+        WM_COMMAND => {
+            match wparam.0 & 0xFFFF {
+                MENU_SETTINGS => open_settings(),
+                MENU_RESET => reset_config(window),
+                MENU_LAST_EVENT => show_last_event(window),
+                MENU_RELOAD => reload_now(),
+                MENU_STOP_LEARNING => stop_learning(window),
+                MENU_DRY_RUN => set_dry_run(!unsafe { DRY_RUN }),
+                MENU_QUIT => unsafe { PostQuitMessage(0) },
+                _ => {}
             }
-            let devn = String::from_utf8_lossy(&name[..rc as usize]).to_lowercase();
-            if devn.starts_with(CONTOUR_ID) && unsafe { data.ri.data.hid.dwSizeHid == 6 } {
-                process_contour_event(&mut data);
-            } else {
-                println!("OtherDev");
+            LRESULT(0)
+        }
+
+        WM_DEVICECHANGE => {
+            if matches!(wparam.0 as u32, DBT_DEVICEARRIVAL | DBT_DEVNODES_CHANGED) {
+                // Restarting the timer pushes re-registration past the last change
+                unsafe { SetTimer(window, TIMER_REREGISTER, REREGISTER_DELAY_MS, None) };
             }
+            LRESULT(1)
+        }
 
+        WM_TIMER if wparam.0 == TIMER_REREGISTER => {
+            unsafe { KillTimer(window, TIMER_REREGISTER) };
+            println!("Devices changed, re-registering raw input");
+            // A reconnected device starts from scratch, the chosen scroll speed stays
+            unsafe {
+                GLOBAL_STATE.last = SystemState::new().last;
+                JOG_POSITION = 0;
+            }
+            // The release of a held button may never arrive
+            stop_all_repeats(window);
+            end_chord_wait(window);
+            unsafe { CHORD_USED = 0 };
+            // The ReadFile thread finds the device again by itself
+            if config().input_mode == InputMode::RawInput {
+                if let Err(err) = register_devices(window) {
+                    toast(Category::Error, &tr(Msg::RegisterFailed, &[&err]));
+                }
+            }
             LRESULT(0)
         }
-        _ => unsafe { DefWindowProcA(window, message, wparam, lparam) },
-    }
-}
 
-fn process_contour_event(data: &mut RawInputWrapper) {
-    let hiddata = unsafe { *(data.ri.data.hid.bRawData.as_ptr() as *const ContourHidEvent) };
-    println!("HID: {:X?}/{}", hiddata, unsafe {
-        data.ri.data.hid.dwCount
-    });
+        WM_TIMER if wparam.0 == TIMER_IDLE => {
+            unsafe {
+                KillTimer(window, TIMER_IDLE);
+                PAUSED = true;
+            }
+            stop_all_repeats(window);
+            println!("Idle, pausing");
+            toast(Category::Action, &tr(Msg::Paused, &[]));
+            update_tooltip();
+            LRESULT(0)
+        }
+
+        WM_TIMER if wparam.0 == TIMER_STARTUP => {
+            unsafe { KillTimer(window, TIMER_STARTUP) };
+            println!("Startup delay over, starting");
+            if let Err(err) = start_input(window) {
+                toast(Category::Error, &err.to_string());
+                unsafe { PostQuitMessage(0) };
+            }
+            LRESULT(0)
+        }
+
+        WM_TIMER if wparam.0 == TIMER_DIAGNOSE => {
+            unsafe { KillTimer(window, TIMER_DIAGNOSE) };
+            finish_diagnose();
+            LRESULT(0)
+        }
+
+        WM_TIMER if wparam.0 == TIMER_JOG_SCROLL => {
+            let x = unsafe { JOG_POSITION };
+            // Also stops once focus moves away, on pausing or once another
+            // mode was applied
+            let on = x != 0
+                && config().jog.mode == JogMode::Scroll
+                && foreground_ok()
+                && !unsafe { USER_PAUSED };
+            if on {
+                send_jog(x);
+            }
+            jog_scroll_timer(window, on);
+            LRESULT(0)
+        }
+
+        WM_TIMER if wparam.0 == TIMER_TARGET_POLL => {
+            poll_target();
+            LRESULT(0)
+        }
+
+        WM_TIMER if wparam.0 == TIMER_CHORD => {
+            // The window passed without a partner; a tick just short of it
+            // waits for the next one
+            let wait = unsafe { CHORD_PENDING };
+            match wait.map(|w| config().chord_step(w, ChordInput::Timer, Instant::now())) {
+                Some(ChordStep::Wait) => {}
+                _ => {
+                    end_chord_wait(window);
+                    if let Some(wait) = wait {
+                        unsafe { LAST_EVENT = Some(ContourEvents::ButtonDown(wait.button)) };
+                        press(window, wait.button);
+                    }
+                }
+            }
+            LRESULT(0)
+        }
+
+        WM_TIMER
+            if (TIMER_REPEAT..TIMER_REPEAT + config::BUTTON_COUNT as usize).contains(&wparam.0) =>
+        {
+            let button = (wparam.0 - TIMER_REPEAT) as u16;
+            match binding_of(button) {
+                Some(_) if held_too_long(button) => {
+                    println!(
+                        "Button {} held over {} ms, releasing",
+                        button,
+                        config().max_hold_ms
+                    );
+                    stop_repeat(window, button);
+                }
+                // Focus moved away mid-hold
+                Some(_) if !foreground_ok() => stop_repeat(window, button),
+                Some(binding) if unsafe { REPEATING } & (1 << button) != 0 => {
+                    unsafe { LAST_EVENT = Some(ContourEvents::ButtonDown(button)) };
+                    perform(binding);
+                    ramp_repeat(window, button, binding);
+                }
+                // Released, or the binding went away with a config change
+                _ => stop_repeat(window, button),
+            }
+            LRESULT(0)
+        }
+
+        WM_INPUT_DEVICE_CHANGE => {
+            if wparam.0 as u32 == GIDC_REMOVAL && lparam.0 == unsafe { CONTOUR_DEVICE.0 } {
+                println!("ShuttlePRO removed");
+                eventlog::write(Level::Warning, "ShuttlePRO removed");
+                unsafe {
+                    CONTOUR_DEVICE = HANDLE(0);
+                    DEVICE_FOUND = false;
+                }
+            }
+            LRESULT(0)
+        }
+
+        WM_HOTKEY => {
+            match wparam.0 as i32 {
+                HOTKEY_RELOAD => reload_now(),
+                HOTKEY_LEARN => stop_learning(window),
+                _ => {}
+            }
+            LRESULT(0)
+        }
+
+        #[cfg(feature = "net")]
+        status::APPWM_STATUS => {
+            let answer = lparam.0 as *mut String;
+            if !answer.is_null() {
+                unsafe { *answer = status().message() };
+            }
+            LRESULT(0)
+        }
+
+        APPWM_PICKED => {
+            picked(HWND(wparam.0 as isize));
+            LRESULT(0)
+        }
+
+        hidread::APPWM_OPENED => {
+            if let Some(model) = Model::ALL.get(wparam.0) {
+                identified(HANDLE(0), *model);
+            }
+            LRESULT(0)
+        }
+
+        hidread::APPWM_REPORT => {
+            process_contour_event(window, &hidread::unpack(wparam, lparam));
+            LRESULT(0)
+        }
+
+        WM_INPUT => {
+            //  println!("WM_INPUT");
+            let mut data: RawInputWrapper = unsafe { mem::zeroed() };
+            let mut size = mem::size_of::<RawInputWrapper>() as u32;
+            let rc = unsafe {
+                GetRawInputData(
+                    HRAWINPUT(lparam.0),
+                    RID_INPUT,
+                    Some(&mut data as *mut RawInputWrapper as *mut ::core::ffi::c_void),
+                    &mut size,
+                    mem::size_of::<RAWINPUTHEADER>() as u32,
+                )
+            };
+            if rc < 1 {
+                return LRESULT(0);
+            }
+            let dev = unsafe { data.ri.header.hDevice };
+            // Known handles skip the name lookup; a handle is not reused
+            // before WM_INPUT_DEVICE_CHANGE reports its device gone
+            let known = dev.0 != 0 && dev == unsafe { CONTOUR_DEVICE };
+            let is_contour = known
+                || match device_name(dev).as_deref().and_then(Model::from_name) {
+                    Some(model) => {
+                        identified(dev, model);
+                        true
+                    }
+                    None => false,
+                };
+            if is_contour && unsafe { data.ri.data.hid.dwSizeHid == 6 } {
+                // bRawData is declared one byte long, the report follows it in the buffer
+                let report = unsafe {
+                    std::slice::from_raw_parts(data.ri.data.hid.bRawData.as_ptr(), REPORT_SIZE)
+                };
+                process_contour_event(window, report);
+            } else {
+                println!("OtherDev");
+            }
+
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcA(window, message, wparam, lparam) },
+    }
+}
+
+/// Size of the name buffer this used to read into; longer names were cut off.
+const OLD_NAME_BUFFER: u32 = 1024;
+
+/// Raw input handle of the ShuttlePRO once a report from it was identified,
+/// cleared when it is unplugged.
+static mut CONTOUR_DEVICE: HANDLE = HANDLE(0);
+
+/// Whether a device was identified and hasn't been unplugged since.
+static mut DEVICE_FOUND: bool = false;
+
+/// Model of `CONTOUR_DEVICE`, bounding the buttons its reports can have.
+static mut MODEL: Model = Model::ShuttleProV2;
+
+/// Report layout from the `CONTOUR_DEVICE` descriptor, if it could be read.
+static mut LAYOUT: Option<hid::Layout> = None;
+
+/// Remember `dev` as the ShuttlePRO and check the config fits the model.
+fn identified(dev: HANDLE, model: Model) {
+    println!("Found {}", model);
+    eventlog::write(Level::Info, &format!("Found {}", model));
+    // Read whatever the decode mode, so the log shows what the descriptor says
+    let layout = if dev.0 != 0 { hid::layout(dev) } else { None };
+    if layout.is_none() {
+        println!("No usable HID descriptor, decoding the fixed layout");
+    }
+    unsafe {
+        CONTOUR_DEVICE = dev;
+        DEVICE_FOUND = true;
+        MODEL = model;
+        LAYOUT = layout;
+    }
+    if let Err(err) = config().check_buttons(model.buttons()) {
+        toast(Category::Error, &tr(Msg::ModelMismatch, &[&model, &err]));
+    }
+}
+
+/// Interface path of a raw input device, however long it is.
+fn device_name(dev: HANDLE) -> Option<Vec<u8>> {
+    // A null buffer makes the call report the length needed, in characters
+    let mut len = 0u32;
+    unsafe { GetRawInputDeviceInfoA(dev, RIDI_DEVICENAME, None, &mut len) };
+    if len == 0 {
+        return None;
+    }
+    if len > OLD_NAME_BUFFER {
+        println!("Device name of {} chars would have been truncated", len);
+    }
+
+    let mut name = vec![0u8; len as usize];
+    let rc = unsafe {
+        GetRawInputDeviceInfoA(
+            dev,
+            RIDI_DEVICENAME,
+            Some(name.as_mut_ptr() as *mut ::core::ffi::c_void),
+            &mut len,
+        )
+    };
+    // (UINT)-1 means the buffer was too small after all
+    if rc < 1 || rc > name.len() as u32 {
+        return None;
+    }
+    name.truncate(rc as usize);
+    Some(name)
+}
+
+/// `--learn` is on: input is shown, not acted on.
+static mut LEARNING: bool = false;
+
+fn start_learning(window: HWND) {
+    unsafe { LEARNING = true };
+    println!("Learn mode on");
+    // Esc ends it, taken from every other program meanwhile
+    let ok = unsafe { RegisterHotKey(window, HOTKEY_LEARN, MOD_NOREPEAT, VK_ESCAPE.0 as u32) };
+    if !ok.as_bool() {
+        println!("RegisterHotKey for Esc failed: {}", Error::from_win32());
+    }
+    learn_message(&tr(Msg::LearnStarted, &[]));
+}
+
+fn stop_learning(window: HWND) {
+    if !unsafe { ptr::replace(&raw mut LEARNING, false) } {
+        return;
+    }
+    unsafe { UnregisterHotKey(window, HOTKEY_LEARN) };
+    println!("Learn mode off");
+    learn_message(&tr(Msg::LearnStopped, &[]));
+}
+
+/// Show what `evt` is, for writing the config. Releases need no showing.
+fn learn(evt: ContourEvents) {
+    let text = match evt {
+        ContourEvents::ButtonDown(b) => tr(Msg::LearnButton, &[&b]),
+        ContourEvents::Jog(x) => tr(Msg::LearnJog, &[&x]),
+        ContourEvents::WheelLeft => tr(Msg::LearnWheel, &[&-1]),
+        ContourEvents::WheelRight => tr(Msg::LearnWheel, &[&1]),
+        ContourEvents::ButtonUp(_) => return,
+    };
+    learn_message(&text);
+}
+
+/// A toast whatever `[notify]` says, as learning is pointless without them.
+fn learn_message(text: &str) {
+    println!("[learn] {}", text);
+    message(
+        &tr(Msg::TitleInfo, &[]),
+        text,
+        config().toasts.style(Category::Action),
+    );
+}
+
+/// Token bucket letting through `rate` a second, in bursts of up to a
+/// quarter second's worth.
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+    /// Takes refused since the last one let through.
+    dropped: u32,
+}
+
+impl Bucket {
+    fn new() -> Self {
+        Bucket {
+            tokens: f64::MAX,
+            at: Instant::now(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether one more may go through now at `rate` a second.
+    fn take(&mut self, rate: u32) -> bool {
+        let now = Instant::now();
+        let burst = (rate / 4).max(1) as f64;
+        let refill = now.duration_since(self.at).as_secs_f64() * rate as f64;
+        self.tokens = (self.tokens + refill).min(burst);
+        self.at = now;
+        if self.tokens < 1.0 {
+            self.dropped += 1;
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Of `rate_cap`.
+static mut BUCKET: Option<Bucket> = None;
+/// Of `wheel.key_rate`.
+static mut WHEEL_KEYS: Option<Bucket> = None;
+/// Buttons whose press was dropped by the cap, so their release is too.
+static mut THROTTLED: u16 = 0;
+
+/// Whether `rate_cap` drops `evt`.
+fn throttled(evt: ContourEvents) -> bool {
+    match evt {
+        ContourEvents::ButtonUp(b) => {
+            let dropped = unsafe { THROTTLED } & (1 << b) != 0;
+            unsafe { THROTTLED &= !(1 << b) };
+            return dropped;
+        }
+        ContourEvents::Jog(raw) if config().jog.centered(raw) == 0 => return false,
+        _ => {}
+    }
+    let cap = config().rate_cap;
+    if cap == 0 {
+        return false;
+    }
+    let bucket = unsafe { global(&raw mut BUCKET).get_or_insert_with(Bucket::new) };
+    if bucket.take(cap) {
+        if bucket.dropped > 0 {
+            println!(
+                "Rate cap: {} events dropped",
+                mem::take(&mut bucket.dropped)
+            );
+        }
+        return false;
+    }
+    if bucket.dropped == 1 {
+        println!("Rate cap of {} events/s reached, dropping events", cap);
+    }
+    if let ContourEvents::ButtonDown(b) = evt {
+        unsafe { THROTTLED |= 1 << b };
+    }
+    true
+}
+
+/// Handle one ShuttlePRO report, from raw input or the ReadFile thread.
+fn process_contour_event(window: HWND, report: &[u8]) {
+    let Some(mut hiddata) = ContourHidEvent::from_bytes(report) else {
+        return;
+    };
+    if config().device.decode == Decode::Descriptor {
+        if let Some(layout) = unsafe { global(&raw mut LAYOUT).as_ref() } {
+            hiddata = layout.decode(report, hiddata).unwrap_or(hiddata);
+        }
+    }
+    println!("HID: {:X?}", hiddata);
+    console::show(&hiddata);
+    if config().idle_pause_ms > 0 {
+        // Re-arming restarts the countdown
+        unsafe { SetTimer(window, TIMER_IDLE, config().idle_pause_ms, None) };
+    }
+    let device = &config().device;
+    if device.check_report && !hiddata.has_signature(device.report_id(unsafe { MODEL })) {
+        println!("Report doesn't look like a ShuttlePRO's, dropped");
+        diagnose::input(report, &[]);
+        return;
+    }
+    // A button the model shouldn't have, e.g. a pressable wheel on some other variant
+    let extra = |keys: u16| keys >> unsafe { MODEL }.buttons();
+    if extra(hiddata.keys) != extra(unsafe { GLOBAL_STATE.last.keys }) {
+        println!(
+            "Key bits {:016b} beyond the {}'s buttons, ignored",
+            hiddata.keys,
+            unsafe { MODEL }
+        );
+    }
     // let mut P = unsafe { (PLAYER.as_ref()) }.unwrap();
-    let evts = unsafe { GLOBAL_STATE.update(hiddata) };
+    let evts = unsafe { global(&raw mut GLOBAL_STATE).update_model(hiddata, MODEL.buttons()) };
 
     println!("EVT={:?}", &evts);
+    diagnose::input(report, &evts);
+    if unsafe { LEARNING } {
+        evts.into_iter().for_each(learn);
+        return;
+    }
+    if unsafe { ptr::replace(&raw mut PAUSED, false) } {
+        println!("Input, resuming");
+        toast(Category::Action, &tr(Msg::Resumed, &[]));
+        update_tooltip();
+        if config().idle_drop_wake {
+            for evt in &evts {
+                if let ContourEvents::ButtonDown(b) = evt {
+                    unsafe { WAKE_KEYS |= 1 << b };
+                }
+            }
+            return;
+        }
+    }
+    // Paused from the tray is inert too, so state still follows the device
+    let inert = unsafe { USER_PAUSED } || !foreground_ok();
+    if unsafe { USER_PAUSED } {
+        println!("Paused, input ignored");
+    } else if inert {
+        println!("Target not in the foreground, ignoring input");
+    }
     for evt in evts {
+        if let ContourEvents::ButtonUp(b) = evt {
+            if unsafe { WAKE_KEYS } & (1 << b) != 0 {
+                unsafe { WAKE_KEYS &= !(1 << b) };
+                continue;
+            }
+        }
+        unsafe { LAST_EVENT = Some(evt) };
+        inspect(evt);
+        if let Some(recorder) = unsafe { global(&raw mut RECORDER).as_mut() } {
+            if let Err(err) = recorder.write(evt) {
+                println!("Recording failed: {}", err);
+            }
+        }
+
+        // The controller follows the device, whatever the focus
+        if !dry_run(format_args!("vJoy {:?}", evt)) {
+            vjoy::feed(evt);
+        }
+
+        #[cfg(feature = "net")]
+        {
+            net::publish(evt);
+            metrics::count_event(evt);
+        }
+
+        if throttled(evt) {
+            continue;
+        }
+
+        #[cfg(feature = "scripting")]
+        if !inert {
+            let profile =
+                unsafe { ACTIVE_PROFILE }.map_or("", |i| config().profiles[i].name.as_str());
+            let zoom = unsafe { GLOBAL_STATE.scroll_zoom };
+            match scripting::run(evt, zoom, profile) {
+                Ok(Some(bindings)) => {
+                    // Built at run time, so never checked with the config
+                    for binding in &bindings {
+                        match config().validate_binding(binding) {
+                            Ok(()) => perform(binding),
+                            Err(err) => toast(Category::Error, &tr(Msg::ScriptFailed, &[&err])),
+                        }
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(err) => toast(Category::Error, &tr(Msg::ScriptFailed, &[&err])),
+            }
+        }
+
         match evt {
-            ContourEvents::Jog(x) => {
-                if x < 0 {
-                    send_key(VK_OEM_4); // [
+            ContourEvents::Jog(raw) => {
+                // Raw position -> clamp and scale -> dispatch; positions that
+                // scale alike are one position, so moving between them is silent
+                let jog = &config().jog;
+                let x = scale_jog(jog.centered(raw), jog.clamp, jog.steps);
+                if x != unsafe { JOG_POSITION } {
+                    // Tracked while inert too, so coming back doesn't replay the move
+                    let previous = unsafe { ptr::replace(&raw mut JOG_POSITION, x) };
+                    unsafe { LAST_EVENT = Some(ContourEvents::Jog(x)) };
+                    if !inert {
+                        send_jog(x);
+                        if let Some(tier) = tier_edge(previous, x) {
+                            tier_changed(tier);
+                        }
+                    }
+                    if config().jog.mode == JogMode::Scroll {
+                        jog_scroll_timer(window, x != 0 && !inert);
+                    }
+                }
+            }
+            // A press from before focus moved away must still stop repeating
+            ContourEvents::ButtonUp(b) if inert => forget_button(window, b),
+            _ if inert => {}
+            ContourEvents::WheelLeft if wheel_step(false) => {
+                send_wheel(Scroll::Left(wheel_steps(false)))
+            }
+            ContourEvents::WheelRight if wheel_step(true) => {
+                send_wheel(Scroll::Right(wheel_steps(true)))
+            }
+            ContourEvents::WheelLeft | ContourEvents::WheelRight => {}
+            ContourEvents::ButtonUp(b) => button_up(window, b),
+            ContourEvents::ButtonDown(b) => button_down(window, b),
+        }
+    }
+}
+
+/// Wheel ticks towards the next step, signed by direction.
+static mut WHEEL_TICKS: i32 = 0;
+
+/// Count a raw tick; true when it completes `wheel.units_per_step` in a row.
+fn wheel_step(right: bool) -> bool {
+    let (ticks, done) = count_tick(unsafe { WHEEL_TICKS }, right, config().wheel.units_per_step);
+    unsafe { WHEEL_TICKS = ticks };
+    done
+}
+
+/// Time and direction (right = true) of the last wheel tick, for acceleration.
+static mut LAST_WHEEL: Option<(Instant, bool)> = None;
+
+/// Scroll steps for a wheel tick, which counts towards acceleration.
+fn wheel_steps(right: bool) -> u16 {
+    let steps = steps_now(right);
+    unsafe { LAST_WHEEL = Some((Instant::now(), right)) };
+    steps
+}
+
+/// Scroll steps a tick would give now: `1 << scroll_zoom`, times
+/// `wheel.acceleration` when it follows a tick the same way within
+/// `wheel.accel_ms`.
+fn steps_now(right: bool) -> u16 {
+    let wheel = &config().wheel;
+    let fast = unsafe { LAST_WHEEL }.is_some_and(|(at, dir)| {
+        dir == right && at.elapsed() < Duration::from_millis(wheel.accel_ms as u64)
+    });
+    let steps = 1u16 << unsafe { GLOBAL_STATE.scroll_zoom };
+    if fast {
+        steps.saturating_mul(wheel.acceleration as u16)
+    } else {
+        steps
+    }
+}
+
+/// A chord button pressed less than `chord_window_ms` ago, waiting for its partner.
+static mut CHORD_PENDING: Option<ChordWait> = None;
+/// Bit `b` is set while button `b` is held as part of a chord that fired.
+static mut CHORD_USED: u16 = 0;
+
+/// A shift button being held.
+struct Shift {
+    button: u16,
+    /// Index into `config().layers`.
+    layer: usize,
+    since: Instant,
+    /// Whether another button went down meanwhile, which rules out a tap.
+    used: bool,
+}
+
+static mut SHIFT: Option<Shift> = None;
+/// The layer each button went down in, so its release and repeats keep
+/// the same binding when the shift is let go first.
+static mut PRESSED_IN: [Option<usize>; config::BUTTON_COUNT as usize] =
+    [None; config::BUTTON_COUNT as usize];
+
+/// `button`'s binding in the layer it was pressed in.
+fn binding_of(button: u16) -> Option<&'static Binding> {
+    config().layer_button(unsafe { PRESSED_IN[button as usize] }, button)
+}
+
+fn button_down(window: HWND, button: u16) {
+    if let Some(layer) = config().layer_of(button) {
+        println!("Layer {} on", layer);
+        unsafe {
+            SHIFT = Some(Shift {
+                button,
+                layer,
+                since: Instant::now(),
+                used: false,
+            })
+        };
+        return;
+    }
+    unsafe {
+        let shift = global(&raw mut SHIFT).as_mut();
+        PRESSED_IN[button as usize] = shift.map(|shift| {
+            shift.used = true;
+            shift.layer
+        });
+    }
+    let now = Instant::now();
+    if let Some(wait) = end_chord_wait(window) {
+        if let ChordStep::Chord(chord) = config().chord_step(wait, ChordInput::Press(button), now) {
+            unsafe { CHORD_USED |= 1 << wait.button | 1 << button };
+            perform(chord);
+            return;
+        }
+        // Not a partner, or too late, so the first press stands on its own
+        press(window, wait.button);
+    }
+
+    if config().in_chord(button) {
+        unsafe {
+            CHORD_PENDING = Some(ChordWait { button, since: now });
+            SetTimer(window, TIMER_CHORD, config().chord_window_ms, None);
+        }
+        return;
+    }
+    press(window, button);
+}
+
+/// Stop waiting for a chord partner, returning the button that waited.
+fn end_chord_wait(window: HWND) -> Option<ChordWait> {
+    let wait = unsafe { ptr::replace(&raw mut CHORD_PENDING, None) };
+    if wait.is_some() {
+        unsafe { KillTimer(window, TIMER_CHORD) };
+    }
+    wait
+}
+
+/// Let go of shift button `button`; if that was a tap, fire its tap action.
+fn shift_up(button: u16) {
+    let Some(shift) = (unsafe { ptr::replace(&raw mut SHIFT, None) }) else {
+        return;
+    };
+    if shift.button != button {
+        unsafe { SHIFT = Some(shift) };
+        return;
+    }
+    println!("Layer {} off", shift.layer);
+    let Some(layer) = config().layers.get(shift.layer) else {
+        return;
+    };
+    if layer.is_tap(shift.used, shift.since, Instant::now()) {
+        if let Some(tap) = &layer.shift_tap_action {
+            perform(tap);
+        }
+    }
+}
+
+fn button_up(window: HWND, button: u16) {
+    if config().layer_of(button).is_some() {
+        shift_up(button);
+        return;
+    }
+    if unsafe { CHORD_USED } & (1 << button) != 0 {
+        unsafe { CHORD_USED &= !(1 << button) };
+        return;
+    }
+    if let Some(wait) = unsafe { CHORD_PENDING } {
+        // Released before a partner came, cancelling the chord, or past the
+        // window before its timer went off
+        if config().chord_step(wait, ChordInput::Release(button), Instant::now())
+            == ChordStep::Alone
+        {
+            end_chord_wait(window);
+            press(window, wait.button);
+        }
+    }
+    release(window, button);
+}
+
+/// A release while inert: clear what the press left behind, as `button_up`
+/// would, without firing anything.
+fn forget_button(window: HWND, button: u16) {
+    if unsafe { REPEATING } & (1 << button) != 0 {
+        stop_repeat(window, button);
+    }
+    unsafe {
+        if let Some(shift) = global(&raw mut SHIFT).take_if(|shift| shift.button == button) {
+            println!("Layer {} off", shift.layer);
+        }
+        if CHORD_PENDING.is_some_and(|wait| wait.button == button) {
+            end_chord_wait(window);
+        }
+        CHORD_USED &= !(1 << button);
+        PRESSED_IN[button as usize] = None;
+    }
+}
+
+/// A press that is not (or no longer) part of a chord.
+fn press(window: HWND, button: u16) {
+    let Some(binding) = binding_of(button) else {
+        if config().button(button).is_some() {
+            println!("Button {} inhibited in layer", button);
+            return;
+        }
+        match config().unmapped {
+            Unmapped::Ignore => {}
+            Unmapped::Toast => toast(Category::Action, &tr(Msg::UnmappedButton, &[&button])),
+            Unmapped::Log => println!("Button {} has no binding", button),
+        }
+        return;
+    };
+    if binding.repeat_while_held {
+        perform(binding);
+        start_repeat(window, button, binding);
+    } else if binding.fire.on_down() || binding.up.is_some() {
+        perform(binding);
+    } else if binding.log {
+        println!("[log] button {} down, fires on release", button);
+    }
+}
+
+/// With an `up` binding, that fires on release and the button's own action
+/// has already fired on press; either way a repeat stops first.
+fn release(window: HWND, button: u16) {
+    let binding = binding_of(button);
+    if unsafe { REPEATING } & (1 << button) != 0 {
+        // Already fired on press
+        stop_repeat(window, button);
+    } else if let Some(binding) = binding.filter(|b| b.fire.on_up() && b.up.is_none()) {
+        perform(binding);
+    }
+    if let Some(up) = binding.and_then(|b| b.up.as_deref()) {
+        perform(up);
+    }
+    unsafe { PRESSED_IN[button as usize] = None };
+}
+
+/// Keep scrolling at `jog.scroll_ms` while `on`, else stop.
+fn jog_scroll_timer(window: HWND, on: bool) {
+    if on {
+        unsafe { SetTimer(window, TIMER_JOG_SCROLL, config().jog.scroll_ms, None) };
+    } else {
+        unsafe { KillTimer(window, TIMER_JOG_SCROLL) };
+    }
+}
+
+/// The ring moved into tier `tier` from another one; -3 to 3 stays in tier 3.
+fn tier_changed(tier: u8) {
+    println!("Jog tier {}", tier);
+    if let Some(binding) = config().jog_tier(tier) {
+        perform(binding);
+    }
+}
+
+fn send_jog(x: i8) {
+    let jog = &config().jog;
+    if jog.mode == JogMode::Absolute {
+        // Only reached on a change, so holding a position fires it once
+        if let Some(binding) = config().jog_position(x) {
+            perform(binding);
+        }
+        return;
+    }
+    if jog.mode == JogMode::Scroll {
+        // Once per call; the timer keeps a held ring going
+        if x != 0 {
+            let (message, sign) = match jog.scroll_axis {
+                ScrollAxis::Vertical => (WM_MOUSEWHEEL, -x.signum() as i16),
+                ScrollAxis::Horizontal => (WM_MOUSEHWHEEL, x.signum() as i16),
+            };
+            let sign = if jog.scroll_invert { -sign } else { sign };
+            post_wheel(message, sign, x.unsigned_abs() as u16, 0);
+        }
+        return;
+    }
+
+    let midi = &config().midi;
+    if midi.enabled {
+        if x != 0 {
+            let value = midi::encode_relative(midi.relative_mode, x);
+            if !dry_run(format_args!("MIDI CC {} = {}", midi.jog_cc, value)) {
+                midi::send_cc(midi.channel, midi.jog_cc, value);
+            }
+        }
+        return;
+    }
+
+    let modifiers = config().jog_modifiers(x);
+    if x < 0 {
+        send_key(config().jog.left.0, &modifiers);
+    }
+    if x > 0 {
+        send_key(config().jog.right.0, &modifiers);
+    }
+}
+
+/// When each action with a `cooldown_ms` last fired.
+static mut COOLDOWNS: Vec<(Action, Instant)> = Vec::new();
+
+/// Whether `binding` is still cooling down; if not, it counts as fired now.
+fn cooling_down(binding: &Binding) -> bool {
+    let Some(ms) = binding.cooldown_ms.filter(|ms| *ms > 0) else {
+        return false;
+    };
+    let cooldowns = unsafe { global(&raw mut COOLDOWNS) };
+    let now = Instant::now();
+    match cooldowns.iter_mut().find(|(a, _)| *a == binding.action) {
+        Some((_, last)) if now.duration_since(*last) < Duration::from_millis(ms as u64) => {
+            return true
+        }
+        Some((_, last)) => *last = now,
+        None => cooldowns.push((binding.action.clone(), now)),
+    }
+    false
+}
+
+/// What the most recent event did, for the tray's "Last event".
+struct Inspect {
+    event: ContourEvents,
+    /// The last binding it fired, `None` for built-in jog and wheel handling.
+    action: Option<Action>,
+    /// `POSTED` when it came in.
+    posted: (u32, u32),
+}
+
+static mut INSPECT: Option<Inspect> = None;
+
+/// Start the snapshot of `evt`, replacing the last one.
+fn inspect(evt: ContourEvents) {
+    unsafe {
+        INSPECT = Some(Inspect {
+            event: evt,
+            action: None,
+            posted: POSTED,
+        })
+    };
+}
+
+fn show_last_event(window: HWND) {
+    let text = match unsafe { global(&raw mut INSPECT).as_ref() } {
+        None => tr(Msg::LastEventNone, &[]),
+        Some(last) => {
+            let nothing = tr(Msg::LastEventNothing, &[]);
+            let action = last
+                .action
+                .as_ref()
+                .map_or(nothing.clone(), |a| format!("{:?}", a));
+            // Resolved only now, to keep window lookups off the input path
+            let target = find_targets().first().map_or(nothing, |&w| {
+                format!("{:#X} \"{}\" ({})", w.0, window_title(w), window_class(w))
+            });
+            // Counts until now, so a repeat still running adds to them
+            let (ok, failed) = unsafe { POSTED };
+            let sent = ok - last.posted.0;
+            let lost = failed - last.posted.1;
+            tr(
+                Msg::LastEvent,
+                &[&format!("{:?}", last.event), &action, &target, &sent, &lost],
+            )
+        }
+    };
+    let title = tr(Msg::MenuLastEvent, &[]);
+    unsafe { MessageBoxW(window, &HSTRING::from(text), &HSTRING::from(title), MB_OK) };
+}
+
+/// Set while a binding with `log = true` runs, for the lines only it prints.
+static mut TRACING: bool = false;
+
+/// Most earlier scroll zoom levels `scroll_zoom_back` can go back through.
+const ZOOM_HISTORY_MAX: usize = 8;
+
+/// Scroll zoom levels before each change, the latest last.
+static mut ZOOM_HISTORY: Vec<u8> = Vec::new();
+
+fn perform(binding: &Binding) {
+    if binding.log {
+        println!(
+            "[log] {:?} fires {:?}",
+            unsafe { LAST_EVENT },
+            binding.action
+        );
+    }
+    if cooling_down(binding) {
+        println!("{:?} cooling down, ignored", binding.action);
+        return;
+    }
+    if let Some(last) = unsafe { global(&raw mut INSPECT).as_mut() } {
+        last.action = Some(binding.action.clone());
+    }
+    if unsafe { DRY_RUN } {
+        println!("[dry-run] {:?}", binding.action);
+        unsafe { DRY_ACTIONS += 1 };
+    }
+    let outer = unsafe { ptr::replace(&raw mut TRACING, TRACING || binding.log) };
+    match &binding.action {
+        Action::Key { key } => {
+            let modifiers = take_sticky();
+            // Keep the order: anything held back goes first
+            flush_queued();
+            if !send_key(key.0, &modifiers) {
+                let policy = config().when_no_target(binding, unsafe { ACTIVE_PROFILE });
+                hold_back(key.0, modifiers, policy);
+            }
+        }
+        Action::Sequence { keys } => {
+            let modifiers = take_sticky();
+            // Held back, a sequence could be cut short or collapsed, so it only goes out whole
+            if find_targets().is_empty() {
+                println!("No VLC, dropping sequence");
+            } else {
+                flush_queued();
+                for key in keys {
+                    send_key(key.0, &modifiers);
                 }
-                if x > 0 {
-                    send_key(VK_OEM_6); // ]
+            }
+        }
+        Action::Char { ch } if dry_run(format_args!("type {:?}", ch)) => {}
+        Action::Char { ch } => {
+            if let Err(err) = action::send_char(*ch) {
+                toast(Category::Error, &tr(Msg::TypeFailed, &[ch, &err]));
+            }
+        }
+        Action::AppCommand { command } => {
+            for target in find_targets() {
+                // wparam is the window the command came from, lparam's high word the
+                // command; FAPPCOMMAND_KEY (0) in its top bits marks it as from a key
+                let lparam = LPARAM((command.0 as isize) << 16);
+                post(target, WM_APPCOMMAND, WPARAM(target.0 as usize), lparam);
+            }
+        }
+        Action::RawMessage {
+            message,
+            wparam,
+            lparam,
+        } => {
+            for target in find_targets() {
+                post(target, *message, WPARAM(*wparam), LPARAM(*lparam));
+            }
+        }
+        Action::OutputReport { vid, pid, report }
+            if dry_run(format_args!(
+                "output report {:02X?} to {:04X}:{:04X}",
+                report, vid, pid
+            )) => {}
+        Action::OutputReport { vid, pid, report } => {
+            let sent = match hidread::find_interface(*vid, *pid) {
+                Some(path) => hid::write_output_report(&path, report),
+                None => {
+                    let device = format!("{:04X}:{:04X}", vid, pid);
+                    Err(app_error(&tr(Msg::NoDevice, &[&device])))
                 }
+            };
+            if let Err(err) = sent {
+                toast(Category::Error, &tr(Msg::OutputReportFailed, &[&err]));
             }
-            ContourEvents::WheelLeft => {
-                let zoom = unsafe { GLOBAL_STATE.scroll_zoom };
-                send_h_wheel(Scroll::Left(1 << zoom));
-            }
-            ContourEvents::WheelRight => {
-                let zoom = unsafe { GLOBAL_STATE.scroll_zoom };
-                send_h_wheel(Scroll::Right(1 << zoom));
-            }
-            ContourEvents::ButtonUp(b) => match b {
-                0..=3 => unsafe {
-                    GLOBAL_STATE.scroll_zoom = b as u8;
-                    message("Info", format!("Scroll speed {}", 1 << b).as_str());
-                },
-                6 => send_key(VK_SPACE),
-                13 | 14 => {
-                    send_key(VK_OEM_PLUS);
-                    message("Info", "Playback speed normal");
+        }
+        Action::StickyModifier { modifier, lock } => {
+            // Pressing again takes the modifier back off
+            let set = unsafe {
+                let bits = global(if *lock {
+                    &raw mut STICKY_LOCKED
+                } else {
+                    &raw mut STICKY_PENDING
+                });
+                *bits ^= modifier.bit();
+                *bits & modifier.bit() != 0
+            };
+            println!("Sticky {:?} {}", modifier, if set { "on" } else { "off" });
+        }
+        Action::ScrollZoom { level } => {
+            unsafe {
+                let previous = ptr::replace(&raw mut GLOBAL_STATE.scroll_zoom, *level);
+                let history = global(&raw mut ZOOM_HISTORY);
+                if previous != *level {
+                    if history.len() == ZOOM_HISTORY_MAX {
+                        history.remove(0);
+                    }
+                    history.push(previous);
                 }
-                _ => {}
-            },
-            ContourEvents::ButtonDown(_) => {}
+            }
+            toast(
+                Category::ScrollSpeed,
+                &tr(Msg::ScrollSpeed, &[&(1 << level)]),
+            );
+        }
+        Action::ScrollZoomBack => match unsafe { global(&raw mut ZOOM_HISTORY).pop() } {
+            Some(level) => {
+                unsafe { GLOBAL_STATE.scroll_zoom = level };
+                println!("Scroll zoom back to {}", level);
+                toast(
+                    Category::ScrollSpeed,
+                    &tr(Msg::ScrollSpeed, &[&(1 << level)]),
+                );
+            }
+            None => toast(Category::ScrollSpeed, &tr(Msg::ScrollZoomNoHistory, &[])),
+        },
+        Action::Command { program, .. } if dry_run(format_args!("run {}", program)) => {}
+        Action::Command { program, args } => {
+            if let Err(err) = action::spawn(program, args, true) {
+                toast(Category::Error, &tr(Msg::RunFailed, &[program, &err]));
+            }
+        }
+        Action::OpenUrl { url } if dry_run(format_args!("open {}", url)) => {}
+        Action::OpenUrl { url } => {
+            if let Err(err) = action::open_url(url) {
+                toast(Category::Error, &tr(Msg::OpenFailed, &[url, &err]));
+            }
+        }
+        Action::Profile { name } => {
+            unsafe { ACTIVE_PROFILE = config().profile_index(name) };
+            update_icon();
+            println!("Profile {}", name);
+            toast(Category::Profile, &tr(Msg::Profile, &[name]));
+        }
+    }
+    if let Some(text) = &binding.toast {
+        let text = match Msg::from_id(text) {
+            // The one id with an argument, filled in as the action does
+            Some(Msg::ScrollSpeed) => tr(
+                Msg::ScrollSpeed,
+                &[&(1u16 << unsafe { GLOBAL_STATE.scroll_zoom })],
+            ),
+            Some(msg) => tr(msg, &[]),
+            None => config::fill(text, placeholder),
+        };
+        toast(Category::Action, &text);
+    }
+    if binding.log {
+        println!(
+            "[log] done, {} posted, {} failed so far",
+            unsafe { POSTED.0 },
+            unsafe { POSTED.1 }
+        );
+    }
+    unsafe { TRACING = outer };
+}
+
+/// The event bindings are firing for, with jog positions already scaled.
+static mut LAST_EVENT: Option<ContourEvents> = None;
+
+/// Value of a toast placeholder, see `config::PLACEHOLDERS`.
+fn placeholder(name: &str) -> Option<String> {
+    let evt = unsafe { LAST_EVENT };
+    let text = match name {
+        "button" => match evt {
+            Some(ContourEvents::ButtonDown(b) | ContourEvents::ButtonUp(b)) => b.to_string(),
+            _ => String::new(),
+        },
+        "value" => match evt {
+            Some(ContourEvents::Jog(x)) => x.to_string(),
+            Some(ContourEvents::WheelLeft) => "-1".to_string(),
+            Some(ContourEvents::WheelRight) => "1".to_string(),
+            Some(ContourEvents::ButtonDown(b) | ContourEvents::ButtonUp(b)) => b.to_string(),
+            None => String::new(),
+        },
+        "dir" => match evt {
+            Some(ContourEvents::Jog(x)) if x < 0 => "left",
+            Some(ContourEvents::Jog(x)) if x > 0 => "right",
+            Some(ContourEvents::WheelLeft) => "left",
+            Some(ContourEvents::WheelRight) => "right",
+            _ => "",
         }
+        .to_string(),
+        "profile" => unsafe { ACTIVE_PROFILE }
+            .map_or_else(String::new, |i| config().profiles[i].name.clone()),
+        // The way the wheel last turned, so acceleration counts
+        "step" => steps_now(unsafe { LAST_WHEEL }.is_some_and(|(_, right)| right)).to_string(),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Whether a target window existed at the last settled poll, `None` before the first.
+static mut TARGET_PRESENT: Option<bool> = None;
+/// A changed answer seen once, confirmed or dropped by the next poll.
+static mut TARGET_CHANGING: bool = false;
+
+/// (Re)start polling for the target window at `target.poll_ms`.
+fn start_target_poll() {
+    let wnd = unsafe { MAIN_WND };
+    if wnd.0 == 0 {
+        return;
+    }
+    match config().target.poll_ms {
+        0 => unsafe {
+            KillTimer(wnd, TIMER_TARGET_POLL);
+        },
+        ms => unsafe {
+            SetTimer(wnd, TIMER_TARGET_POLL, ms, None);
+        },
     }
+    poll_target();
 }
 
-fn send_key(key: VIRTUAL_KEY) {
-    let vlc = unsafe { FindWindowA(s!("Qt5QWindowIcon"), None) };
+/// Whether a target window was in the foreground when last checked.
+static mut FOREGROUND: bool = true;
+
+/// False when `require_foreground` is set and no target window is in front,
+/// updating the tooltip when that changes.
+fn foreground_ok() -> bool {
+    let ok =
+        !config().target.require_foreground || { find_candidates().contains(&foreground_window()) };
+    if ok != unsafe { ptr::replace(&raw mut FOREGROUND, ok) } {
+        update_tooltip();
+    }
+    ok
+}
 
-    if vlc.0 > 0 {
-        println!("Found VLC, sending {:?}", key);
+fn poll_target() {
+    // Picks up windows opened since the last lookup
+    let present = !refresh_candidates().is_empty();
+    foreground_ok();
+    if present {
+        flush_queued();
+    }
+    let name = &config().target.name;
+    match unsafe { TARGET_PRESENT } {
+        Some(was) if was == present => unsafe { TARGET_CHANGING = false },
+        // Startup: report how things are without a toast
+        None => {
+            unsafe { TARGET_PRESENT = Some(present) };
+            update_tooltip();
+        }
+        // A window being recreated shouldn't read as closed and ready again
+        Some(_) if !unsafe { TARGET_CHANGING } => unsafe { TARGET_CHANGING = true },
+        Some(_) => {
+            unsafe {
+                TARGET_PRESENT = Some(present);
+                TARGET_CHANGING = false;
+            }
+            let msg = if present {
+                Msg::TargetReady
+            } else {
+                Msg::TargetClosed
+            };
+            toast(Category::Target, &tr(msg, &[name]));
+            update_tooltip();
+        }
+    }
+}
 
-        unsafe { PostMessageA(vlc, WM_KEYDOWN, WPARAM(key.0 as usize), LPARAM(1)) };
+/// What a status query is answered with.
+#[cfg(feature = "net")]
+fn status() -> schema::Status {
+    let target = find_targets().first().map(|wnd| schema::Target {
+        handle: wnd.0,
+        class: window_class(*wnd),
+        title: window_title(*wnd),
+    });
+    unsafe {
+        schema::Status {
+            device: DEVICE_FOUND.then(|| { MODEL }.to_string()),
+            profile: ACTIVE_PROFILE.map(|i| config().profiles[i].name.clone()),
+            paused: PAUSED || USER_PAUSED,
+            scroll_zoom: GLOBAL_STATE.scroll_zoom,
+            target,
+        }
+    }
+}
 
-        unsafe {
-            PostMessageA(
+fn update_tooltip() {
+    let msg = match unsafe { (PAUSED || USER_PAUSED, TARGET_PRESENT, FOREGROUND) } {
+        (true, _, _) => Msg::TipPaused,
+        (false, Some(false), _) => Msg::TipNoTarget,
+        (false, _, false) => Msg::TipBackground,
+        _ => Msg::TipReady,
+    };
+    let mut tip = tr(msg, &[&config().target.name]);
+    if unsafe { DRY_RUN } {
+        tip = tr(Msg::TipDryRun, &[&tip]);
+    }
+    let tip: Vec<u16> = tip.encode_utf16().collect();
+    let mut nid = NOTIFYICONDATAW {
+        cbSize: mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: unsafe { MAIN_WND },
+        uID: 1,
+        uFlags: NIF_TIP,
+        ..Default::default()
+    };
+    let len = min(tip.len(), nid.szTip.len() - 1);
+    nid.szTip[..len].copy_from_slice(&tip[..len]);
+    unsafe { Shell_NotifyIconW(NIM_MODIFY, &nid) };
+}
+
+/// The window picked with `--pick-window` and its class, which all input
+/// goes to while it lives.
+static mut PINNED: Option<(HWND, String)> = None;
+static mut PICK_HOOK: Option<HHOOK> = None;
+
+/// `--pick-window`: take the next left click anywhere as the window to pin.
+/// `SetCapture` only sees clicks over other programs' windows if the button
+/// went down on ours, which is hidden, so a low-level mouse hook takes it.
+fn start_pick(instance: HMODULE) {
+    match unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(pick_hook), instance, 0) } {
+        Ok(hook) => {
+            unsafe { PICK_HOOK = Some(hook) };
+            toast(Category::Action, &tr(Msg::PickWindow, &[]));
+        }
+        Err(err) => toast(Category::Error, &err.to_string()),
+    }
+}
+
+/// Runs on the main thread, so it only posts what was clicked and keeps
+/// the click from reaching the window.
+unsafe extern "system" fn pick_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 {
+        let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+        match wparam.0 as u32 {
+            WM_LBUTTONDOWN => {
+                let wnd = GetAncestor(WindowFromPoint(info.pt), GA_ROOT);
+                PostMessageW(MAIN_WND, APPWM_PICKED, WPARAM(wnd.0 as usize), LPARAM(0));
+                return LRESULT(1);
+            }
+            WM_RBUTTONDOWN => {
+                PostMessageW(MAIN_WND, APPWM_PICKED, WPARAM(0), LPARAM(0));
+                return LRESULT(1);
+            }
+            // The press was ours, so is the release
+            WM_LBUTTONUP | WM_RBUTTONUP => return LRESULT(1),
+            _ => {}
+        }
+    }
+    CallNextHookEx(HHOOK(0), code, wparam, lparam)
+}
+
+fn picked(wnd: HWND) {
+    if let Some(hook) = unsafe { ptr::replace(&raw mut PICK_HOOK, None) } {
+        unsafe { UnhookWindowsHookEx(hook) };
+    }
+    if wnd.0 == 0 {
+        toast(Category::Action, &tr(Msg::PickCancelled, &[]));
+        return;
+    }
+    let class = window_class(wnd);
+    println!("Picked window {:#X} ({})", wnd.0, class);
+    if config().target.remember_pick {
+        let text = format!("{:#X} {}", wnd.0, class);
+        if let Err(err) = std::fs::write(pick_path(), text) {
+            println!("Unable to remember the pick: {}", err);
+        }
+    }
+    unsafe { PINNED = Some((wnd, class)) };
+    toast(Category::Target, &tr(Msg::Picked, &[&window_title(wnd)]));
+    poll_target();
+}
+
+/// Where `target.remember_pick` keeps the pick, next to the config.
+fn pick_path() -> PathBuf {
+    config_path().with_file_name("picked-window.txt")
+}
+
+/// Pin the remembered pick again, if that window is still open, e.g. after
+/// restarting the program; it can't outlive the window.
+fn load_pick() {
+    let Ok(text) = std::fs::read_to_string(pick_path()) else {
+        return;
+    };
+    let Some((wnd, class)) = text.trim().split_once(' ') else {
+        return;
+    };
+    let Ok(wnd) = isize::from_str_radix(wnd.trim_start_matches("0x"), 16) else {
+        return;
+    };
+    let wnd = HWND(wnd);
+    if unsafe { IsWindow(wnd) }.as_bool() && window_class(wnd) == class {
+        println!("Back to picked window {:#X} ({})", wnd.0, class);
+        unsafe { PINNED = Some((wnd, class.to_string())) };
+    } else {
+        let _ = std::fs::remove_file(pick_path());
+    }
+}
+
+/// The picked window while it lives, checked by class too, since a closed
+/// window's handle can be reused. Once it is gone, matching takes over.
+fn pinned() -> Option<HWND> {
+    let (wnd, class) = unsafe { global(&raw mut PINNED).as_ref() }?;
+    if unsafe { IsWindow(*wnd) }.as_bool() && window_class(*wnd) == *class {
+        return Some(*wnd);
+    }
+    unsafe { PINNED = None };
+    let _ = std::fs::remove_file(pick_path());
+    toast(
+        Category::Target,
+        &tr(Msg::PickedClosed, &[&config().target.name]),
+    );
+    None
+}
+
+fn window_class(wnd: HWND) -> String {
+    let mut class = [0u16; 256];
+    let len = unsafe { GetClassNameW(wnd, &mut class) } as usize;
+    String::from_utf16_lossy(&class[..len])
+}
+
+/// Windows found per profile (`None` for no profile) at the last lookup.
+static mut TARGET_CACHE: Vec<(Option<usize>, Vec<HWND>)> = Vec::new();
+
+/// Live top-level windows matching the active profile's target, in Z order.
+/// Remembered per profile until they close, or the next target poll.
+fn find_candidates() -> Vec<HWND> {
+    if let Some(wnd) = pinned() {
+        return vec![wnd];
+    }
+    let profile = unsafe { ACTIVE_PROFILE };
+    let cache = unsafe { global(&raw mut TARGET_CACHE) };
+    if let Some((_, found)) = cache.iter_mut().find(|(p, _)| *p == profile) {
+        // Windows may close between lookup and posting
+        found.retain(|w| unsafe { IsWindow(*w) }.as_bool());
+        if !found.is_empty() {
+            return found.clone();
+        }
+    }
+    refresh_candidates()
+}
+
+/// Look the active profile's target windows up again.
+fn refresh_candidates() -> Vec<HWND> {
+    if let Some(wnd) = pinned() {
+        return vec![wnd];
+    }
+    let profile = unsafe { ACTIVE_PROFILE };
+    let matcher = config().matcher(profile);
+    let class = CString::new(matcher.class.as_str()).unwrap_or_default();
+    let class = if matcher.class.is_empty() {
+        PCSTR::null()
+    } else {
+        PCSTR(class.as_ptr() as *const u8)
+    };
+    let mut found = Vec::new();
+    let mut wnd = HWND(0);
+    loop {
+        wnd = unsafe { FindWindowExA(None, wnd, class, None) };
+        if wnd.0 == 0 {
+            break;
+        }
+        if window_matches(wnd, &matcher) {
+            found.push(wnd);
+        }
+    }
+
+    let cache = unsafe { global(&raw mut TARGET_CACHE) };
+    cache.retain(|(p, _)| *p != profile);
+    cache.push((profile, found.clone()));
+    found
+}
+
+/// Whether `wnd` passes the title and process parts of `matcher`.
+fn window_matches(wnd: HWND, matcher: &config::Matcher) -> bool {
+    if let Some(title) = &matcher.title {
+        if !window_title(wnd).contains(title.as_str()) {
+            return false;
+        }
+    }
+    match &matcher.process {
+        Some(process) => window_process(wnd).is_some_and(|p| p.eq_ignore_ascii_case(process)),
+        None => true,
+    }
+}
+
+fn window_title(wnd: HWND) -> String {
+    let mut title = [0u16; 256];
+    let len = unsafe { GetWindowTextW(wnd, &mut title) } as usize;
+    String::from_utf16_lossy(&title[..len])
+}
+
+/// File name of the program owning `wnd`.
+fn window_process(wnd: HWND) -> Option<String> {
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(wnd, Some(&mut pid)) };
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) }.ok()?;
+    let mut path = [0u16; 1024];
+    let mut len = path.len() as u32;
+    let ok = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(path.as_mut_ptr()),
+            &mut len,
+        )
+    };
+    unsafe { CloseHandle(process) };
+    if !ok.as_bool() {
+        return None;
+    }
+    let path = String::from_utf16_lossy(&path[..len as usize]);
+    path.rsplit('\\').next().map(str::to_string)
+}
+
+/// The candidate the user is looking at, or the first one if none has focus.
+fn select_target(candidates: &[HWND]) -> Option<HWND> {
+    let foreground = foreground_window();
+    candidates
+        .iter()
+        .find(|w| **w == foreground)
+        .or_else(|| candidates.first())
+        .copied()
+}
+
+/// Every window that should get input: all candidates with `broadcast`, else the selected one.
+fn find_targets() -> Vec<HWND> {
+    let candidates = find_candidates();
+    let targets: Vec<HWND> = if config().target.broadcast {
+        candidates
+    } else {
+        select_target(&candidates).into_iter().collect()
+    };
+    if !config().target.uwp {
+        return targets;
+    }
+    targets.into_iter().map(content_window).collect()
+}
+
+/// Store apps draw in a `Windows.UI.Core.CoreWindow` inside their
+/// `ApplicationFrameWindow` host, and only it acts on keys. The frame
+/// itself when it is something else, or the app is suspended, which
+/// takes the content window out of the frame.
+fn content_window(wnd: HWND) -> HWND {
+    if window_class(wnd) != "ApplicationFrameWindow" {
+        return wnd;
+    }
+    let content = unsafe { FindWindowExA(wnd, None, s!("Windows.UI.Core.CoreWindow"), None) };
+    if content.0 == 0 {
+        println!("No content window in {:#X}, sending to the frame", wnd.0);
+        return wnd;
+    }
+    content
+}
+
+/// Media and volume keys act system-wide once a window passes them on, so
+/// broadcasting them would repeat the effect once per window.
+fn is_global_key(key: VIRTUAL_KEY) -> bool {
+    (VK_VOLUME_MUTE.0..=VK_MEDIA_PLAY_PAUSE.0).contains(&key.0)
+}
+
+/// Post `key` with `modifiers` held around it; false if there was no target.
+fn send_key(key: VIRTUAL_KEY, modifiers: &[VIRTUAL_KEY]) -> bool {
+    let mut targets = find_targets();
+    if is_global_key(key) {
+        targets.truncate(1);
+    }
+    if targets.is_empty() {
+        println!("No VLC");
+        #[cfg(feature = "net")]
+        metrics::count_no_target();
+        return false;
+    }
+
+    // With Alt down, Windows reports keys as system keys with the context bit set
+    let (down, up, context) = if modifiers.contains(&VK_MENU) {
+        (WM_SYSKEYDOWN, WM_SYSKEYUP, 1 << 29)
+    } else {
+        (WM_KEYDOWN, WM_KEYUP, 0)
+    };
+
+    for vlc in targets {
+        println!("Found {:?}, sending {:?} {:?}", vlc, modifiers, key);
+
+        let keys = modifiers.iter().chain(std::iter::once(&key));
+        for k in keys.clone() {
+            post(vlc, down, WPARAM(k.0 as usize), LPARAM(1 | context));
+        }
+        for k in keys.rev() {
+            post(
                 vlc,
-                WM_KEYUP,
-                WPARAM(key.0 as usize),
-                LPARAM(1 | 1 << 30 | 1 << 31),
-            )
-        };
+                up,
+                WPARAM(k.0 as usize),
+                LPARAM(1 | context | 1 << 30 | 1 << 31),
+            );
+        }
+        #[cfg(feature = "net")]
+        metrics::count_key();
+    }
+    true
+}
+
+/// Messages posted fine and failed to post, for `--selftest`.
+static mut POSTED: (u32, u32) = (0, 0);
+
+fn post(wnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) {
+    let (w, l) = (wparam.0, lparam.0);
+    if dry_run(format_args!(
+        "post {:#X} {:#X} {:#X} to {:#X}",
+        message, w, l, wnd.0
+    )) {
+        return;
+    }
+    let ok = unsafe { PostMessageA(wnd, message, wparam, lparam) }.as_bool();
+    if unsafe { TRACING } {
+        println!(
+            "[log] post {:#X} {:#X} {:#X} to {:#X}: {}",
+            message, wparam.0, lparam.0, wnd.0, ok
+        );
+    }
+    unsafe {
+        if ok {
+            POSTED.0 += 1;
+        } else {
+            POSTED.1 += 1;
+        }
+    }
+}
+
+/// `--dry-run` or the tray item is on: actions are resolved and logged, but
+/// nothing is posted, typed, run or fed to MIDI and vJoy.
+static mut DRY_RUN: bool = false;
+/// Actions performed since the dry run started.
+static mut DRY_ACTIONS: u32 = 0;
+
+fn set_dry_run(on: bool) {
+    let actions = unsafe {
+        DRY_RUN = on;
+        ptr::replace(&raw mut DRY_ACTIONS, 0)
+    };
+    println!("Dry run {}", if on { "on" } else { "off" });
+    if on {
+        toast(Category::Action, &tr(Msg::DryRunOn, &[]));
     } else {
+        toast(Category::Action, &tr(Msg::DryRunOff, &[&actions]));
+    }
+    update_tooltip();
+}
+
+/// Whether to skip doing `what`, logging it instead, as this is a dry run.
+fn dry_run(what: std::fmt::Arguments) -> bool {
+    if unsafe { DRY_RUN } {
+        println!("[dry-run] {}", what);
+    }
+    unsafe { DRY_RUN }
+}
+
+/// Most keys `when_no_target = "queue"` holds back.
+const QUEUE_MAX: usize = 64;
+
+/// Key actions waiting for a target window, oldest first.
+static mut QUEUED: Vec<(VIRTUAL_KEY, Vec<VIRTUAL_KEY>)> = Vec::new();
+
+/// A key action that found no target, handled per its `when_no_target`.
+fn hold_back(key: VIRTUAL_KEY, modifiers: Vec<VIRTUAL_KEY>, policy: NoTarget) {
+    let queued = unsafe { global(&raw mut QUEUED) };
+    match policy {
+        NoTarget::Drop => return,
+        NoTarget::Queue if queued.len() >= QUEUE_MAX => {
+            println!("Queue full, dropping {:?}", key);
+            return;
+        }
+        NoTarget::Queue => {}
+        NoTarget::Latest => queued.retain(|(k, _)| *k != key),
+    }
+    println!("Holding {:?} until a target appears", key);
+    queued.push((key, modifiers));
+}
+
+/// Send the held-back keys, if there is somewhere to send them now.
+fn flush_queued() {
+    if unsafe { global(&raw mut QUEUED).is_empty() } || find_targets().is_empty() {
+        return;
+    }
+    for (key, modifiers) in unsafe { ptr::replace(&raw mut QUEUED, Vec::new()) } {
+        send_key(key, &modifiers);
+    }
+}
+
+/// Windows for wheel messages and the cursor position to report with them.
+fn find_wheel_targets() -> (Vec<HWND>, LPARAM) {
+    match config().wheel.target {
+        WheelTarget::Class => (find_targets(), LPARAM(0)),
+        WheelTarget::UnderCursor => {
+            let mut pt = POINT::default();
+            if !unsafe { GetCursorPos(&mut pt) }.as_bool() {
+                return (Vec::new(), LPARAM(0));
+            }
+            let wnd = unsafe { WindowFromPoint(pt) };
+            let pos = ((pt.y as u16 as isize) << 16) | pt.x as u16 as isize;
+            let found = if wnd.0 > 0 { vec![wnd] } else { Vec::new() };
+            (found, LPARAM(pos))
+        }
+    }
+}
+
+/// Do what the active profile's `wheel_action` says for a wheel tick.
+fn send_wheel(scroll: Scroll) {
+    let (right, steps) = match scroll {
+        Scroll::Left(n) => (false, n),
+        Scroll::Right(n) => (true, n),
+    };
+    match config().wheel_action(unsafe { ACTIVE_PROFILE }) {
+        WheelAction::HScroll => post_wheel(WM_MOUSEHWHEEL, if right { 1 } else { -1 }, steps, 0),
+        // Turning right moves on, which is down the page
+        WheelAction::VScroll => post_wheel(WM_MOUSEWHEEL, if right { -1 } else { 1 }, steps, 0),
+        WheelAction::Zoom => send_zoom(right, steps),
+        WheelAction::Volume => {
+            send_key(if right { VK_VOLUME_UP } else { VK_VOLUME_DOWN }, &[]);
+        }
+        WheelAction::Keys => {
+            let wheel = &config().wheel;
+            send_key(if right { wheel.right.0 } else { wheel.left.0 }, &[]);
+        }
+        WheelAction::Key => {
+            let wheel = &config().wheel;
+            let key = if right { wheel.right.0 } else { wheel.left.0 };
+            let bucket = unsafe { global(&raw mut WHEEL_KEYS).get_or_insert_with(Bucket::new) };
+            for _ in 0..steps {
+                // Logged once when keys start being dropped and once they stop
+                if !bucket.take(wheel.key_rate) {
+                    if bucket.dropped == 1 {
+                        println!(
+                            "Wheel key rate of {}/s reached, dropping keys",
+                            wheel.key_rate
+                        );
+                    }
+                    continue;
+                }
+                if bucket.dropped > 0 {
+                    println!(
+                        "Wheel key rate: {} keys dropped",
+                        mem::take(&mut bucket.dropped)
+                    );
+                }
+                send_key(key, &[]);
+            }
+        }
+        // Scroll zoom and acceleration decide how many steps, so how far a tick seeks
+        WheelAction::Jog => {
+            for _ in 0..steps {
+                send_jog(if right { 1 } else { -1 });
+            }
+        }
+    }
+}
+
+/// Zoom in (`right`) or out by `steps`, the way `wheel.zoom_with` says.
+fn send_zoom(right: bool, steps: u16) {
+    let delta = if right { 1 } else { -1 };
+    match config().wheel.zoom_with {
+        ZoomWith::Post => post_wheel(WM_MOUSEWHEEL, delta as i16, steps, MK_CONTROL),
+        ZoomWith::Input => {
+            if dry_run(format_args!("Ctrl+wheel {} x{}", delta, steps)) {
+                return;
+            }
+            if let Err(err) = action::send_ctrl_wheel(delta, steps) {
+                println!("Zoom input failed: {}", err);
+            }
+        }
+        ZoomWith::Keys => {
+            let key = if right { VK_OEM_PLUS } else { VK_OEM_MINUS };
+            for _ in 0..steps {
+                send_key(key, &[VK_CONTROL]);
+            }
+        }
+    }
+}
+
+/// Wheel units owed to the next tick in the same direction; signed by direction.
+static mut WHEEL_CARRY: i32 = 0;
+
+/// Post wheel `message`s worth `steps` times `wheel.detent_units` in
+/// direction `sign`, in messages of `wheel.delta` units each, with `keys`
+/// as the held MK_* state. What doesn't divide evenly carries over.
+fn post_wheel(message: u32, sign: i16, steps: u16, keys: u32) {
+    let (targets, pos) = find_wheel_targets();
+    if targets.is_empty() {
         println!("No VLC");
+        #[cfg(feature = "net")]
+        metrics::count_no_target();
+    }
+
+    let wheel = &config().wheel;
+    let carry = unsafe { WHEEL_CARRY };
+    // A change of direction forfeits what was owed the other way
+    let carry = if carry.signum() == -(sign as i32) {
+        0
+    } else {
+        carry.abs()
+    };
+    let total = steps as i32 * wheel.detent_units as i32 + carry;
+    let delta = wheel.delta.max(1) as i32;
+    let count = total / delta;
+    unsafe { WHEEL_CARRY = (total % delta) * sign as i32 };
+
+    let ev = ((delta as i16 * sign) as u16 as usize) << 16 | keys as usize;
+    for vlc in targets {
+        println!(
+            "Found {:?}, sending mouse {:#X} {} x{}",
+            vlc,
+            message,
+            delta * sign as i32,
+            count
+        );
+        for _ in 0..count {
+            post(vlc, message, WPARAM(ev), pos);
+        }
+    }
+}
+
+/// Replace the config file with the defaults after asking, keeping `.bak` of the old one.
+fn reset_config(window: HWND) {
+    let answer = unsafe {
+        MessageBoxW(
+            window,
+            &HSTRING::from(tr(Msg::ResetConfirm, &[])),
+            w!("Contour Controller"),
+            MB_YESNO | MB_ICONQUESTION,
+        )
+    };
+    if answer != IDYES {
+        return;
+    }
+
+    let path = config_path();
+    if let Err(err) = config::reset(&path) {
+        toast(Category::Error, &format!("{}: {}", path.display(), err));
+        return;
+    }
+    // A broken lower layer still can't take the mapping away
+    if reload_config() {
+        toast(
+            Category::Action,
+            &tr(Msg::ConfigWritten, &[&path.display()]),
+        );
     }
 }
 
-fn send_h_wheel(scroll: Scroll) {
-    let vlc = unsafe { FindWindowA(s!("Qt5QWindowIcon"), None) };
+fn open_settings() {
+    if let Err(err) = settings::open() {
+        toast(Category::Error, &err.to_string());
+    }
+}
 
-    if vlc.0 > 0 {
-        println!("Found VLC, sending mouse {:?}", scroll);
+/// Paused from the tray: input is ignored until clicked again, unlike the
+/// idle pause, which any input ends.
+static mut USER_PAUSED: bool = false;
 
-        let (dir, steps) = match scroll {
-            Scroll::Left(n) => (-1, n),
-            Scroll::Right(n) => (1, n),
-        };
-        let ev = (dir as u16 as usize) << 16;
-        for _ in 0..steps {
-            unsafe { PostMessageA(vlc, WM_MOUSEHWHEEL, WPARAM(ev), LPARAM(0)) };
+fn toggle_pause(window: HWND) {
+    let paused = unsafe {
+        USER_PAUSED = !USER_PAUSED;
+        USER_PAUSED
+    };
+    println!(
+        "{}",
+        if paused {
+            "Paused from the tray"
+        } else {
+            "Resumed from the tray"
         }
+    );
+    if paused {
+        stop_all_repeats(window);
+        jog_scroll_timer(window, false);
+        toast(Category::Action, &tr(Msg::TrayPaused, &[]));
     } else {
-        println!("No VLC");
+        toast(Category::Action, &tr(Msg::Resumed, &[]));
+    }
+    update_tooltip();
+}
+
+fn show_tray_menu(window: HWND) {
+    let Ok(menu) = (unsafe { CreatePopupMenu() }) else {
+        return;
+    };
+    let learning =
+        unsafe { LEARNING }.then(|| (MENU_STOP_LEARNING, tr(Msg::MenuStopLearning, &[])));
+    let items = learning.into_iter().chain([
+        (MENU_SETTINGS, tr(Msg::MenuSettings, &[])),
+        (MENU_RELOAD, tr(Msg::MenuReload, &[])),
+        (MENU_RESET, tr(Msg::MenuReset, &[])),
+        (MENU_LAST_EVENT, tr(Msg::MenuLastEvent, &[])),
+        (MENU_DRY_RUN, tr(Msg::MenuDryRun, &[])),
+        (MENU_QUIT, tr(Msg::MenuQuit, &[])),
+    ]);
+    for (id, text) in items {
+        let checked = id == MENU_DRY_RUN && unsafe { DRY_RUN };
+        let flags = if checked {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        unsafe { AppendMenuW(menu, flags, id, &HSTRING::from(text)) };
+    }
+
+    let mut pt = POINT::default();
+    unsafe {
+        GetCursorPos(&mut pt);
+        // Without this the menu stays open after clicking elsewhere
+        SetForegroundWindow(window);
+        TrackPopupMenu(menu, TPM_RIGHTBUTTON, pt.x, pt.y, 0, window, None);
+        DestroyMenu(menu);
     }
 }
 
+fn base_icon() -> HICON {
+    unsafe { LoadIconA(None, PCSTR(IDI_INFORMATION as *const u8)).unwrap() }
+}
+
 fn register_icon(hwnd: HWND) {
-    let icon = unsafe { LoadIconA(None, PCSTR(IDI_INFORMATION as *const u8)).unwrap() };
+    let icon = base_icon();
     let mut nid = NOTIFYICONDATAA {
         cbSize: mem::size_of::<NOTIFYICONDATAA>() as u32,
         hWnd: hwnd,
@@ -356,18 +2779,81 @@ fn register_icon(hwnd: HWND) {
     unsafe { Shell_NotifyIconA(NIM_ADD, &nid) };
 }
 
+/// Tray icon with the active profile's number, `None` when showing the plain one.
+static mut BADGE_ICON: Option<HICON> = None;
+
+/// Hidden window owning the tray icon.
+static mut MAIN_WND: HWND = HWND(0);
+
+/// Redraw the tray icon for the active profile, numbered from 1.
+fn update_icon() {
+    let hwnd = unsafe { MAIN_WND };
+    if hwnd.0 == 0 {
+        return;
+    }
+    let base = base_icon();
+    let icon = match unsafe { ACTIVE_PROFILE } {
+        Some(i) => badge::render(base, &(i + 1).to_string()).unwrap_or_else(|err| {
+            println!("Badge failed, keeping the plain icon: {}", err);
+            base
+        }),
+        None => base,
+    };
+    let nid = NOTIFYICONDATAA {
+        cbSize: mem::size_of::<NOTIFYICONDATAA>() as u32,
+        hWnd: hwnd,
+        uID: 1,
+        uFlags: NIF_ICON,
+        hIcon: icon,
+        ..Default::default()
+    };
+    unsafe { Shell_NotifyIconA(NIM_MODIFY, &nid) };
+
+    let old = unsafe { ptr::replace(&raw mut BADGE_ICON, (icon != base).then_some(icon)) };
+    if let Some(old) = old {
+        unsafe { DestroyIcon(old) };
+    }
+}
+
 fn fill_slice(s: &mut [u8], data: &str) {
     let data = data.as_bytes();
     let len = min(data.len(), s.len());
     s[..len].copy_from_slice(&data[..len]);
 }
 
-fn message(title: &str, text: &str) {
+/// Toast `text` if the active profile wants `category`, styled per its `[toasts]` entry.
+fn toast(category: Category, text: &str) {
+    if category == Category::Error {
+        eventlog::write(Level::Error, text);
+    }
+    // Config errors and --init are reported before there is a config
+    let Some(config) = (unsafe { global(&raw mut CONFIG).as_ref() }) else {
+        let title = match category {
+            Category::Error => tr(Msg::TitleError, &[]),
+            _ => tr(Msg::TitleInfo, &[]),
+        };
+        message(&title, text, ToastStyle::default());
+        return;
+    };
+    let notify = config.notify(unsafe { ACTIVE_PROFILE });
+    if !notify.enabled(category) {
+        println!("Toast suppressed: {}", text);
+        return;
+    }
+    let title = match (category, notify.title) {
+        (Category::Error, _) => tr(Msg::TitleError, &[]),
+        (_, Some(title)) => title,
+        (_, None) => tr(Msg::TitleInfo, &[]),
+    };
+    message(&title, text, config.toasts.style(category));
+}
+
+fn message(title: &str, text: &str, style: ToastStyle) {
     Toast::new(Toast::POWERSHELL_APP_ID)
         .title(title)
         .text1(text)
-        .sound(Some(Sound::SMS))
-        .duration(Duration::Short)
+        .sound(style.sound.sound())
+        .duration(style.duration.duration())
         .show()
         .expect("unable to toast");
 }
@@ -0,0 +1,98 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use shuttle_pro_rs::schema::TYPE_NAMES;
+use shuttle_pro_rs::{ContourEvents, ShuttleEvent};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::net;
+
+/// Decoded events, indexed by `SHUTTLE_EVENT_*`.
+static EVENTS: [AtomicU64; 5] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+static KEYS_SENT: AtomicU64 = AtomicU64::new(0);
+static NO_TARGET: AtomicU64 = AtomicU64::new(0);
+
+pub fn count_event(evt: ContourEvents) {
+    EVENTS[ShuttleEvent::from(evt).kind as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// A key press posted to one window.
+pub fn count_key() {
+    KEYS_SENT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A key or scroll dropped as no target window was found.
+pub fn count_no_target() {
+    NO_TARGET.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counters in the Prometheus text exposition format.
+fn render() -> String {
+    let mut out = String::new();
+    out.push_str("# HELP shuttle_events_total Events decoded from the device.\n");
+    out.push_str("# TYPE shuttle_events_total counter\n");
+    for (name, count) in TYPE_NAMES.iter().zip(&EVENTS) {
+        let _ = writeln!(
+            out,
+            "shuttle_events_total{{type=\"{}\"}} {}",
+            name,
+            count.load(Ordering::Relaxed)
+        );
+    }
+    out.push_str("# HELP shuttle_keys_sent_total Key presses posted to target windows.\n");
+    out.push_str("# TYPE shuttle_keys_sent_total counter\n");
+    let _ = writeln!(
+        out,
+        "shuttle_keys_sent_total {}",
+        KEYS_SENT.load(Ordering::Relaxed)
+    );
+    out.push_str(
+        "# HELP shuttle_no_target_total Keys and scrolls dropped for lack of a target window.\n",
+    );
+    out.push_str("# TYPE shuttle_no_target_total counter\n");
+    let _ = writeln!(
+        out,
+        "shuttle_no_target_total {}",
+        NO_TARGET.load(Ordering::Relaxed)
+    );
+    out
+}
+
+/// Answer every HTTP request on `address` with the counters.
+pub fn start(address: String) {
+    net::serve(|_events| async move {
+        let listener = match TcpListener::bind(address.as_str()).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                println!("Metrics bind to {} failed: {}", address, err);
+                return;
+            }
+        };
+        println!("Serving metrics on http://{}/metrics", address);
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(async move {
+                // Any path gets the metrics, so the request itself is not parsed
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let body = render();
+                let response = format!(
+                    "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
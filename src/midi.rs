@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use windows::core::Result;
+#[cfg(feature = "midi")]
+use windows::Win32::Media::Audio::{
+    midiOutClose, midiOutOpen, midiOutShortMsg, CALLBACK_NULL, HMIDIOUT,
+};
+
+use crate::app_error;
+#[cfg(not(feature = "midi"))]
+use crate::{i18n::Msg, tr};
+
+/// How a signed encoder step is packed into a 7-bit CC value. The names
+/// follow the "Relative 1/2/3" modes most DAWs offer for endless encoders.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelativeMode {
+    /// Two's complement: +1 = 0x01, -1 = 0x7F.
+    #[default]
+    #[serde(alias = "relative1")]
+    TwosComplement,
+    /// Binary offset around 64: +1 = 0x41, -1 = 0x3F.
+    #[serde(alias = "relative2")]
+    BinaryOffset,
+    /// Sign bit: +1 = 0x01, -1 = 0x41.
+    #[serde(alias = "relative3")]
+    SignBit,
+}
+
+/// CC value for a step of `delta`, clamped to the ±63 every mode can express.
+pub fn encode_relative(mode: RelativeMode, delta: i8) -> u8 {
+    let delta = delta.clamp(-63, 63);
+    let magnitude = delta.unsigned_abs();
+    match (mode, delta < 0) {
+        (RelativeMode::TwosComplement, false) => magnitude,
+        (RelativeMode::TwosComplement, true) => 128 - magnitude,
+        (RelativeMode::BinaryOffset, _) => (64 + delta as i16) as u8,
+        (RelativeMode::SignBit, false) => magnitude,
+        (RelativeMode::SignBit, true) => 0x40 | magnitude,
+    }
+}
+
+#[cfg(feature = "midi")]
+static mut MIDI_OUT: HMIDIOUT = HMIDIOUT(0);
+
+/// Open output port `device`, closing any previously opened one.
+#[cfg(feature = "midi")]
+pub fn open(device: u32) -> Result<()> {
+    close();
+    let mut out = HMIDIOUT(0);
+    let rc = unsafe { midiOutOpen(&mut out, device, 0, 0, CALLBACK_NULL) };
+    if rc != 0 {
+        return Err(app_error(&format!(
+            "midiOutOpen({}) failed with MMRESULT {}",
+            device, rc
+        )));
+    }
+    println!("Opened MIDI output {}", device);
+    unsafe { MIDI_OUT = out };
+    Ok(())
+}
+
+#[cfg(feature = "midi")]
+pub fn close() {
+    let out = unsafe { MIDI_OUT };
+    if out.0 != 0 {
+        unsafe { midiOutClose(out) };
+        unsafe { MIDI_OUT = HMIDIOUT(0) };
+    }
+}
+
+/// Send a control change; dropped if no port is open.
+#[cfg(feature = "midi")]
+pub fn send_cc(channel: u8, cc: u8, value: u8) {
+    let out = unsafe { MIDI_OUT };
+    if out.0 == 0 {
+        println!("No MIDI output");
+        return;
+    }
+    let msg =
+        0xB0 | (channel as u32 & 0x0F) | (cc as u32 & 0x7F) << 8 | (value as u32 & 0x7F) << 16;
+    println!("MIDI CC {} {} = {:#04X}", channel, cc, value);
+    unsafe { midiOutShortMsg(out, msg) };
+}
+
+#[cfg(not(feature = "midi"))]
+pub fn open(_device: u32) -> Result<()> {
+    Err(app_error(&tr(Msg::BackendDisabled, &[&"MIDI", &"midi"])))
+}
+
+#[cfg(not(feature = "midi"))]
+pub fn send_cc(_channel: u8, _cc: u8, _value: u8) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_relative_bytes_per_mode() {
+        let deltas = [1, -1, 5, -5, 63, -63, 100, -128];
+        let bytes = |mode| deltas.map(|d| encode_relative(mode, d));
+        assert_eq!(
+            bytes(RelativeMode::TwosComplement),
+            [0x01, 0x7F, 0x05, 0x7B, 0x3F, 0x41, 0x3F, 0x41]
+        );
+        assert_eq!(
+            bytes(RelativeMode::BinaryOffset),
+            [0x41, 0x3F, 0x45, 0x3B, 0x7F, 0x01, 0x7F, 0x01]
+        );
+        assert_eq!(
+            bytes(RelativeMode::SignBit),
+            [0x01, 0x41, 0x05, 0x45, 0x3F, 0x7F, 0x3F, 0x7F]
+        );
+    }
+}
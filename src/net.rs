@@ -0,0 +1,60 @@
+use std::future::Future;
+use std::sync::mpsc;
+use std::thread;
+
+use shuttle_pro_rs::ContourEvents;
+use tokio::runtime::{Builder, Handle};
+use tokio::sync::broadcast;
+
+/// Events each server may fall behind by before it loses the oldest ones.
+const QUEUE: usize = 64;
+
+static mut RUNTIME: Option<Handle> = None;
+static mut EVENTS: Option<broadcast::Sender<ContourEvents>> = None;
+
+/// Start the runtime on its own thread; servers are added with `serve`.
+pub fn start() -> std::io::Result<()> {
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new()
+        .name("net".to_string())
+        .spawn(move || {
+            // The handle is only valid while the runtime lives, so keep it here
+            let _ = tx.send(runtime.handle().clone());
+            runtime.block_on(std::future::pending::<()>());
+        })?;
+
+    let handle = rx.recv().map_err(std::io::Error::other)?;
+    let (events, _) = broadcast::channel(QUEUE);
+    unsafe {
+        RUNTIME = Some(handle);
+        EVENTS = Some(events);
+    }
+    println!("Net runtime started");
+    Ok(())
+}
+
+/// Run a server on the runtime, fed with every event from now on. A server
+/// too slow to keep up gets `RecvError::Lagged` and misses the oldest events.
+pub fn serve<F>(server: impl FnOnce(broadcast::Receiver<ContourEvents>) -> F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (Some(runtime), Some(events)) = (unsafe {
+        (
+            crate::global(&raw mut RUNTIME).as_ref(),
+            crate::global(&raw mut EVENTS).as_ref(),
+        )
+    }) else {
+        return;
+    };
+    runtime.spawn(server(events.subscribe()));
+}
+
+/// Hand `evt` to the servers without waiting for them.
+pub fn publish(evt: ContourEvents) {
+    if let Some(events) = unsafe { crate::global(&raw mut EVENTS).as_ref() } {
+        // Fails only when no server is listening
+        let _ = events.send(evt);
+    }
+}
@@ -0,0 +1,134 @@
+//! Event recordings, as timestamped [`ContourEvents`] relative to the start
+//! of the session.
+//!
+//! Binary files start with [`MAGIC`], followed by 6-byte records: the
+//! timestamp in ms as a little-endian `u32`, the `SHUTTLE_EVENT_*` kind and
+//! the value as a signed byte. CSV files have a `timestamp_ms,type,value`
+//! header and one event per line, `type` being one of
+//! [`TYPE_NAMES`], as in the JSON messages of [`crate::schema`].
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::schema::TYPE_NAMES;
+use crate::{ContourEvents, ShuttleEvent};
+
+pub const MAGIC: &[u8; 8] = b"SHUTREC1";
+const CSV_HEADER: &str = "timestamp_ms,type,value";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    Csv,
+}
+
+impl Format {
+    /// CSV for a `.csv` file, binary otherwise.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::Binary,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "binary" => Some(Format::Binary),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Appends events to a recording as they happen; every record is written
+/// straight through, so an abrupt exit loses nothing.
+pub struct Recorder {
+    out: File,
+    format: Format,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, format: Format) -> io::Result<Recorder> {
+        let mut out = File::create(path)?;
+        match format {
+            Format::Binary => out.write_all(MAGIC)?,
+            Format::Csv => writeln!(out, "{}", CSV_HEADER)?,
+        }
+        Ok(Recorder {
+            out,
+            format,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn write(&mut self, evt: ContourEvents) -> io::Result<()> {
+        let ms = self.start.elapsed().as_millis().min(u32::MAX as u128) as u32;
+        let ShuttleEvent { kind, value } = evt.into();
+        match self.format {
+            Format::Binary => {
+                let mut record = [0u8; 6];
+                record[..4].copy_from_slice(&ms.to_le_bytes());
+                record[4] = kind as u8;
+                record[5] = value as i8 as u8;
+                self.out.write_all(&record)
+            }
+            Format::Csv => writeln!(self.out, "{},{},{}", ms, TYPE_NAMES[kind as usize], value),
+        }
+    }
+}
+
+/// Read a recording in either format, telling them apart by [`MAGIC`].
+pub fn load(path: &Path) -> io::Result<Vec<(u32, ContourEvents)>> {
+    let data = fs::read(path)?;
+    match data.strip_prefix(MAGIC.as_slice()) {
+        Some(records) => load_binary(records),
+        None => load_csv(&String::from_utf8_lossy(&data)),
+    }
+}
+
+fn invalid(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+fn load_binary(records: &[u8]) -> io::Result<Vec<(u32, ContourEvents)>> {
+    if !records.len().is_multiple_of(6) {
+        return Err(invalid("truncated recording".to_string()));
+    }
+    records
+        .chunks_exact(6)
+        .map(|r| {
+            let ms = u32::from_le_bytes([r[0], r[1], r[2], r[3]]);
+            let evt = ShuttleEvent {
+                kind: r[4] as u32,
+                value: r[5] as i8 as i32,
+            };
+            let evt = ContourEvents::try_from(evt)
+                .map_err(|_| invalid(format!("bad event kind {} at {} ms", r[4], ms)))?;
+            Ok((ms, evt))
+        })
+        .collect()
+}
+
+fn load_csv(text: &str) -> io::Result<Vec<(u32, ContourEvents)>> {
+    let mut events = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line == CSV_HEADER {
+            continue;
+        }
+        let bad = || invalid(format!("line {}: {:?}", n + 1, line));
+        let mut fields = line.split(',').map(str::trim);
+        let ms = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad)?;
+        let kind = fields
+            .next()
+            .and_then(|f| TYPE_NAMES.iter().position(|t| *t == f))
+            .ok_or_else(bad)? as u32;
+        let value = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad)?;
+        let evt = ContourEvents::try_from(ShuttleEvent { kind, value }).map_err(|_| bad())?;
+        events.push((ms, evt));
+    }
+    Ok(events)
+}
@@ -0,0 +1,142 @@
+//! The one message format of every event output beyond the raw bytes of
+//! the UDP binary format: a single-line JSON object,
+//!
+//! ```json
+//! {"v":1,"type":"jog","value":-3}
+//! ```
+//!
+//! `v` is [`VERSION`], `type` one of [`TYPE_NAMES`] or `heartbeat`, and
+//! `value` the [`ShuttleEvent`] value: jog position -7..7, button 0-14, 0
+//! for wheel ticks. Heartbeats have no `value`.
+//!
+//! Status is read with a request/response exchange: the request is the
+//! line `{"v":1,"type":"status"}`, the answer one line (wrapped here),
+//!
+//! ```text
+//! {"v":1,"type":"status","device":"ShuttlePRO v2","profile":null,
+//!  "paused":false,"scroll_zoom":2,
+//!  "target":{"handle":132456,"class":"Qt5QWindowIcon","title":"VLC"}}
+//! ```
+//!
+//! with `null` for no device, profile or target window. Any other request
+//! gets `{"v":1,"type":"error","message":...}`. Requests never change state.
+//!
+//! Consumers should ignore fields they don't know. Adding a field keeps the
+//! version; renaming, removing or changing the meaning of one bumps it. A
+//! device index will be added once more than one device is supported.
+
+use crate::{ContourEvents, ShuttleEvent};
+
+/// Bumped on every breaking change to the messages.
+pub const VERSION: u32 = 1;
+
+/// `type` of each event, indexed by `SHUTTLE_EVENT_*`.
+pub const TYPE_NAMES: [&str; 5] = [
+    "jog",
+    "wheel_left",
+    "wheel_right",
+    "button_up",
+    "button_down",
+];
+
+/// `type` of a heartbeat, sent by outputs that have them.
+pub const HEARTBEAT: &str = "heartbeat";
+
+/// The message for `evt`.
+pub fn event(evt: ContourEvents) -> String {
+    let ShuttleEvent { kind, value } = evt.into();
+    format!(
+        "{{\"v\":{},\"type\":\"{}\",\"value\":{}}}",
+        VERSION, TYPE_NAMES[kind as usize], value
+    )
+}
+
+/// The message for a heartbeat.
+pub fn heartbeat() -> String {
+    format!("{{\"v\":{},\"type\":\"{}\"}}", VERSION, HEARTBEAT)
+}
+
+/// `type` of a status request and its answer.
+pub const STATUS: &str = "status";
+
+/// What a status request is answered with, see the module docs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Model of the device, if one is connected.
+    pub device: Option<String>,
+    /// Name of the active profile.
+    pub profile: Option<String>,
+    /// Idle-paused, or paused from the tray.
+    pub paused: bool,
+    pub scroll_zoom: u8,
+    pub target: Option<Target>,
+}
+
+/// The window input currently goes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub handle: isize,
+    pub class: String,
+    pub title: String,
+}
+
+impl Status {
+    pub fn message(&self) -> String {
+        let target = match &self.target {
+            Some(t) => format!(
+                "{{\"handle\":{},\"class\":{},\"title\":{}}}",
+                t.handle,
+                quote(&t.class),
+                quote(&t.title)
+            ),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"v\":{},\"type\":\"{}\",\"device\":{},\"profile\":{},\"paused\":{},\"scroll_zoom\":{},\"target\":{}}}",
+            VERSION,
+            STATUS,
+            optional(self.device.as_deref()),
+            optional(self.profile.as_deref()),
+            self.paused,
+            self.scroll_zoom,
+            target
+        )
+    }
+}
+
+/// Whether request line `line` asks for the status. Only the `type` is
+/// looked at, so requests may carry fields later versions add.
+pub fn is_status_request(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.starts_with('{') && line.contains(&format!("\"type\":\"{}\"", STATUS))
+}
+
+/// The answer to a request that can't be served.
+pub fn error(message: &str) -> String {
+    format!(
+        "{{\"v\":{},\"type\":\"error\",\"message\":{}}}",
+        VERSION,
+        quote(message)
+    )
+}
+
+/// `text` as a JSON string.
+fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn optional(text: Option<&str>) -> String {
+    text.map_or("null".to_string(), quote)
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use shuttle_pro_rs::ContourEvents;
+use windows::core::Result;
+
+use crate::app_error;
+use crate::config::Binding;
+
+static mut SCRIPT: Option<(Engine, AST)> = None;
+/// Time each run may take.
+static mut TIMEOUT: Duration = Duration::ZERO;
+/// Point past which the running script is aborted.
+static mut DEADLINE: Option<Instant> = None;
+
+/// Compile the script at `path`; each run may take up to `timeout_ms`.
+pub fn load(path: &Path, timeout_ms: u32) -> Result<()> {
+    let mut engine = Engine::new();
+    engine.on_progress(|_| match unsafe { DEADLINE } {
+        Some(deadline) if Instant::now() > deadline => Some("script timed out".into()),
+        _ => None,
+    });
+    let ast = engine
+        .compile_file(path.to_path_buf())
+        .map_err(|e| app_error(&format!("{}: {}", path.display(), e)))?;
+    println!("Loaded script {}", path.display());
+    unsafe {
+        TIMEOUT = Duration::from_millis(timeout_ms as u64);
+        SCRIPT = Some((engine, ast));
+    }
+    Ok(())
+}
+
+/// Run the script for `evt`. `None` when it returned nothing, so the event
+/// goes on to the configured bindings; otherwise the bindings it asked for.
+pub fn run(evt: ContourEvents, scroll_zoom: u8, profile: &str) -> Result<Option<Vec<Binding>>> {
+    let Some((engine, ast)) = (unsafe { crate::global(&raw mut SCRIPT).as_ref() }) else {
+        return Ok(None);
+    };
+
+    let (kind, value) = match evt {
+        ContourEvents::Jog(x) => ("jog", x as i64),
+        ContourEvents::WheelLeft => ("wheel_left", 0),
+        ContourEvents::WheelRight => ("wheel_right", 0),
+        ContourEvents::ButtonUp(b) => ("button_up", b as i64),
+        ContourEvents::ButtonDown(b) => ("button_down", b as i64),
+    };
+    let mut event = Map::new();
+    event.insert("type".into(), kind.into());
+    event.insert("value".into(), value.into());
+
+    let mut scope = Scope::new();
+    scope.push("event", event);
+    scope.push("scroll_zoom", scroll_zoom as i64);
+    scope.push("profile", profile.to_string());
+
+    unsafe { DEADLINE = Some(Instant::now() + TIMEOUT) };
+    let result = engine.eval_ast_with_scope::<Dynamic>(&mut scope, ast);
+    unsafe { DEADLINE = None };
+    let result = result.map_err(|e| app_error(&e.to_string()))?;
+
+    if result.is_unit() {
+        return Ok(None);
+    }
+    // A single binding or an array of them, as maps like the config's
+    let items = if result.is_map() {
+        vec![result]
+    } else {
+        result
+            .into_array()
+            .map_err(|_| app_error("script must return a map or an array of maps"))?
+    };
+    items
+        .iter()
+        .map(|item| {
+            rhai::serde::from_dynamic::<Binding>(item).map_err(|e| app_error(&e.to_string()))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
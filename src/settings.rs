@@ -0,0 +1,391 @@
+use windows::core::{s, w, Result, HSTRING, PCWSTR};
+use windows::Win32::Foundation::{HMODULE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::Graphics::Gdi::{GetStockObject, DEFAULT_GUI_FONT, HBRUSH};
+use windows::Win32::System::LibraryLoader::GetModuleHandleA;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::action::Action;
+use crate::config::{Binding, Config, BUTTON_COUNT};
+use crate::i18n::Msg;
+use crate::keys::Key;
+use crate::tr;
+
+// Control styles and messages, from winuser.h
+const CBS_DROPDOWNLIST: u32 = 0x0003;
+const ES_AUTOHSCROLL: u32 = 0x0080;
+const BS_DEFPUSHBUTTON: u32 = 0x0001;
+const CB_ADDSTRING: u32 = 0x0143;
+const CB_GETCURSEL: u32 = 0x0147;
+const CB_SETCURSEL: u32 = 0x014E;
+const COLOR_BTNFACE: isize = 15;
+
+const ID_SAVE: i32 = 1;
+const ID_CANCEL: i32 = 2;
+const ID_KIND: i32 = 1000;
+const ID_ARG: i32 = 2000;
+const ID_JOG_LEFT: i32 = 3000;
+const ID_JOG_RIGHT: i32 = 3001;
+
+const ROW_HEIGHT: i32 = 28;
+
+/// Action types offered per button, in combo box order. Bindings this
+/// window can't edit show as the last entry and are saved unchanged.
+const KINDS: &[&str] = &[
+    "none",
+    "key",
+    "scroll_zoom",
+    "command",
+    "open_url",
+    "profile",
+    "(unchanged)",
+];
+const KIND_KEEP: usize = KINDS.len() - 1;
+
+static mut SETTINGS_WND: HWND = HWND(0);
+
+/// The open settings window, for `IsDialogMessage` in the message loop.
+pub fn window() -> HWND {
+    unsafe { SETTINGS_WND }
+}
+
+/// Show the settings window, or bring it forward if it is already open.
+pub fn open() -> Result<()> {
+    if window().0 != 0 {
+        unsafe { SetForegroundWindow(window()) };
+        return Ok(());
+    }
+
+    let instance = unsafe { GetModuleHandleA(None) }?;
+    let class = s!("contour_settings_window");
+    let wc = WNDCLASSA {
+        hCursor: unsafe { LoadCursorW(None, IDC_ARROW)? },
+        hInstance: instance,
+        lpszClassName: class,
+        hbrBackground: HBRUSH(COLOR_BTNFACE + 1),
+        lpfnWndProc: Some(settings_proc),
+        ..Default::default()
+    };
+    // Fails harmlessly when the class is left over from an earlier open
+    unsafe { RegisterClassA(&wc) };
+
+    let rows = BUTTON_COUNT as i32 + 2;
+    let wnd = unsafe {
+        CreateWindowExA(
+            WINDOW_EX_STYLE::default(),
+            class,
+            s!("Contour Controller"),
+            WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            540,
+            rows * ROW_HEIGHT + 90,
+            None,
+            None,
+            instance,
+            None,
+        )
+    };
+    if wnd.0 == 0 {
+        return Err(windows::core::Error::from_win32());
+    }
+    unsafe { SETTINGS_WND = wnd };
+
+    build(wnd, instance, crate::config());
+    unsafe {
+        ShowWindow(wnd, SW_SHOW);
+        SetForegroundWindow(wnd);
+    }
+    Ok(())
+}
+
+fn control(
+    parent: HWND,
+    instance: HMODULE,
+    class: PCWSTR,
+    text: &str,
+    style: u32,
+    id: i32,
+    rect: (i32, i32, i32, i32),
+) -> HWND {
+    let wnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            class,
+            &HSTRING::from(text),
+            WS_CHILD | WS_VISIBLE | WINDOW_STYLE(style),
+            rect.0,
+            rect.1,
+            rect.2,
+            rect.3,
+            parent,
+            HMENU(id as isize),
+            instance,
+            None,
+        )
+    };
+    let font = unsafe { GetStockObject(DEFAULT_GUI_FONT) };
+    unsafe { SendMessageW(wnd, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1)) };
+    wnd
+}
+
+/// One row per button (action type and its argument) plus the jog keys.
+fn build(wnd: HWND, instance: HMODULE, config: &Config) {
+    let edit = WS_BORDER.0 | WS_TABSTOP.0 | ES_AUTOHSCROLL;
+    for b in 0..BUTTON_COUNT {
+        let y = 10 + b as i32 * ROW_HEIGHT;
+        let id = b as i32;
+        let label = tr(Msg::SettingsButton, &[&b]);
+        control(
+            wnd,
+            instance,
+            w!("STATIC"),
+            &label,
+            0,
+            -1,
+            (10, y + 3, 90, 20),
+        );
+        let combo = control(
+            wnd,
+            instance,
+            w!("COMBOBOX"),
+            "",
+            CBS_DROPDOWNLIST | WS_VSCROLL.0 | WS_TABSTOP.0,
+            ID_KIND + id,
+            (100, y, 130, 200),
+        );
+        for kind in KINDS {
+            let kind = HSTRING::from(*kind);
+            unsafe {
+                SendMessageW(
+                    combo,
+                    CB_ADDSTRING,
+                    WPARAM(0),
+                    LPARAM(kind.as_ptr() as isize),
+                )
+            };
+        }
+        let (kind, arg) = describe(config.button(b));
+        unsafe { SendMessageW(combo, CB_SETCURSEL, WPARAM(kind), LPARAM(0)) };
+        control(
+            wnd,
+            instance,
+            w!("EDIT"),
+            &arg,
+            edit,
+            ID_ARG + id,
+            (240, y, 270, 22),
+        );
+    }
+
+    let y = 10 + BUTTON_COUNT as i32 * ROW_HEIGHT;
+    let jog = [
+        (Msg::SettingsJogLeft, ID_JOG_LEFT, config.jog.left),
+        (Msg::SettingsJogRight, ID_JOG_RIGHT, config.jog.right),
+    ];
+    for (i, (msg, id, key)) in jog.into_iter().enumerate() {
+        let y = y + i as i32 * ROW_HEIGHT;
+        control(
+            wnd,
+            instance,
+            w!("STATIC"),
+            &tr(msg, &[]),
+            0,
+            -1,
+            (10, y + 3, 220, 20),
+        );
+        control(
+            wnd,
+            instance,
+            w!("EDIT"),
+            &key.to_string(),
+            edit,
+            id,
+            (240, y, 270, 22),
+        );
+    }
+
+    let y = y + 2 * ROW_HEIGHT + 10;
+    let button = WS_TABSTOP.0;
+    control(
+        wnd,
+        instance,
+        w!("BUTTON"),
+        &tr(Msg::SettingsSave, &[]),
+        button | BS_DEFPUSHBUTTON,
+        ID_SAVE,
+        (320, y, 90, 26),
+    );
+    control(
+        wnd,
+        instance,
+        w!("BUTTON"),
+        &tr(Msg::SettingsCancel, &[]),
+        button,
+        ID_CANCEL,
+        (420, y, 90, 26),
+    );
+}
+
+/// Combo box index and argument text shown for a binding.
+fn describe(binding: Option<&Binding>) -> (usize, String) {
+    match binding.map(|b| &b.action) {
+        None => (0, String::new()),
+        Some(Action::Key { key }) => (1, key.to_string()),
+        Some(Action::ScrollZoom { level }) => (2, level.to_string()),
+        Some(Action::Command { program, args }) => {
+            let words: Vec<String> = std::iter::once(program)
+                .chain(args)
+                .map(|w| {
+                    if w.contains(char::is_whitespace) {
+                        format!("\"{}\"", w)
+                    } else {
+                        w.clone()
+                    }
+                })
+                .collect();
+            (3, words.join(" "))
+        }
+        Some(Action::OpenUrl { url }) => (4, url.clone()),
+        Some(Action::Profile { name }) => (5, name.clone()),
+        #[allow(unreachable_patterns)]
+        Some(other) => (KIND_KEEP, format!("{:?}", other)),
+    }
+}
+
+/// Split on whitespace, keeping "quoted words" together.
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn text_of(wnd: HWND, id: i32) -> String {
+    let item = unsafe { GetDlgItem(wnd, id) };
+    let len = unsafe { GetWindowTextLengthW(item) } as usize;
+    let mut buf = vec![0u16; len + 1];
+    let n = unsafe { GetWindowTextW(item, &mut buf) } as usize;
+    String::from_utf16_lossy(&buf[..n]).trim().to_string()
+}
+
+/// Build the edited config from the controls; bindings keep their `toast` and repeat settings.
+fn collect(wnd: HWND, current: &Config) -> std::result::Result<Config, String> {
+    let mut config = current.clone();
+    for b in 0..BUTTON_COUNT {
+        let id = b as i32;
+        let combo = unsafe { GetDlgItem(wnd, ID_KIND + id) };
+        let kind = unsafe { SendMessageW(combo, CB_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+        let arg = text_of(wnd, ID_ARG + id);
+        let action = match kind {
+            1 => Action::Key {
+                key: Key::parse(&arg)
+                    .ok_or_else(|| format!("button {}: unknown key {:?}", b, arg))?,
+            },
+            2 => Action::ScrollZoom {
+                level: arg
+                    .parse()
+                    .map_err(|_| format!("button {}: bad level {:?}", b, arg))?,
+            },
+            3 => {
+                let mut words = split_words(&arg).into_iter();
+                Action::Command {
+                    program: words.next().unwrap_or_default(),
+                    args: words.collect(),
+                }
+            }
+            4 => Action::OpenUrl { url: arg },
+            5 => Action::Profile { name: arg },
+            k if k == KIND_KEEP as isize => continue,
+            _ => {
+                config.buttons.remove(&b.to_string());
+                continue;
+            }
+        };
+        let binding = match current.button(b) {
+            Some(old) => Binding {
+                action,
+                ..old.clone()
+            },
+            None => Binding::new(action),
+        };
+        config.buttons.insert(b.to_string(), binding);
+    }
+
+    for (id, key) in [
+        (ID_JOG_LEFT, &mut config.jog.left),
+        (ID_JOG_RIGHT, &mut config.jog.right),
+    ] {
+        let name = text_of(wnd, id);
+        *key = Key::parse(&name).ok_or_else(|| format!("jog: unknown key {:?}", name))?;
+    }
+
+    config.validate()?;
+    Ok(config)
+}
+
+fn save(wnd: HWND) {
+    let result = collect(wnd, crate::config()).and_then(|config| {
+        crate::config::save(
+            unsafe { crate::global(&raw mut crate::CONFIG_PATHS) },
+            &config,
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(config)
+    });
+    match result {
+        Ok(config) => {
+            crate::apply_config(config);
+            unsafe { DestroyWindow(wnd) };
+        }
+        Err(err) => {
+            // Stay open so the mistake can be fixed
+            unsafe {
+                MessageBoxW(
+                    wnd,
+                    &HSTRING::from(err),
+                    &HSTRING::from(tr(Msg::TitleError, &[])),
+                    MB_OK | MB_ICONERROR,
+                )
+            };
+        }
+    }
+}
+
+extern "system" fn settings_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        WM_COMMAND => {
+            match (wparam.0 & 0xFFFF) as i32 {
+                ID_SAVE => save(window),
+                ID_CANCEL => unsafe {
+                    DestroyWindow(window);
+                },
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            unsafe { SETTINGS_WND = HWND(0) };
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcA(window, message, wparam, lparam) },
+    }
+}
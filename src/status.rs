@@ -0,0 +1,72 @@
+//! Read-only status queries over TCP, answered line by line with the
+//! messages of [`shuttle_pro_rs::schema`]: send `{"v":1,"type":"status"}`
+//! and a newline, get the status back as one line. A connection may ask
+//! any number of times.
+//!
+//! The state lives on the window thread, so each query is sent there and
+//! answered from the window procedure.
+
+use shuttle_pro_rs::schema;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::SendMessageW;
+
+use crate::net;
+
+/// Sent to the main window by a query, lparam pointing to the `String` to
+/// put the answer in.
+pub const APPWM_STATUS: u32 = crate::APPWM_ICONNOTIFY + 4;
+
+/// Answer status requests on `address`.
+pub fn start(address: String) {
+    net::serve(|_events| async move {
+        let listener = match TcpListener::bind(address.as_str()).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                println!("Status bind to {} failed: {}", address, err);
+                return;
+            }
+        };
+        println!("Serving status on tcp://{}", address);
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(client(stream));
+        }
+    });
+}
+
+async fn client(stream: TcpStream) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let mut answer = if schema::is_status_request(&line) {
+            query().await
+        } else {
+            schema::error("unknown request")
+        };
+        answer.push('\n');
+        if write.write_all(answer.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// The status from the window thread, waited for off the runtime thread.
+async fn query() -> String {
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut answer = String::new();
+        let lparam = LPARAM(&mut answer as *mut String as isize);
+        unsafe { SendMessageW(crate::MAIN_WND, APPWM_STATUS, WPARAM(0), lparam) };
+        answer
+    })
+    .await
+    .unwrap_or_default();
+    if answer.is_empty() {
+        schema::error("no answer from the window")
+    } else {
+        answer
+    }
+}
@@ -0,0 +1,82 @@
+//! One datagram per event, two bytes:
+//!
+//! | byte | meaning                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | kind, the `SHUTTLE_EVENT_*` value: 0 jog, 1 wheel left,  |
+//! |      | 2 wheel right, 3 button up, 4 button down                |
+//! | 1    | value as a signed byte: jog position -7..7, button 0-14, |
+//! |      | 0 for wheel ticks                                        |
+//!
+//! With `format = "json"`, each datagram is instead the message of
+//! [`shuttle_pro_rs::schema`], versioned so listeners can detect changes.
+//!
+//! With `heartbeat_ms` set, `[HEARTBEAT, 0]` (or a JSON heartbeat) is sent
+//! whenever that long passes without an event, so listeners can tell idle
+//! from gone.
+
+use std::time::Duration;
+
+use shuttle_pro_rs::{schema, ContourEvents, ShuttleEvent};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+
+use crate::config::UdpFormat;
+use crate::net;
+
+/// Kind byte of a heartbeat datagram, past every `SHUTTLE_EVENT_*`.
+const HEARTBEAT: u8 = 0xFF;
+
+/// The datagram for `evt`, a heartbeat if `None`.
+fn packet(format: UdpFormat, evt: Option<ContourEvents>) -> Vec<u8> {
+    match (format, evt) {
+        (UdpFormat::Binary, Some(evt)) => {
+            let evt = ShuttleEvent::from(evt);
+            vec![evt.kind as u8, evt.value as i8 as u8]
+        }
+        (UdpFormat::Binary, None) => vec![HEARTBEAT, 0],
+        (UdpFormat::Json, Some(evt)) => schema::event(evt).into_bytes(),
+        (UdpFormat::Json, None) => schema::heartbeat().into_bytes(),
+    }
+}
+
+/// Send every event to `address` from an ephemeral local port, and a
+/// heartbeat after each `heartbeat_ms` (0 never) without one.
+pub fn start(address: String, format: UdpFormat, heartbeat_ms: u32) {
+    net::serve(|mut events| async move {
+        let socket = match UdpSocket::bind("127.0.0.1:0").await {
+            Ok(socket) => socket,
+            Err(err) => {
+                println!("UDP bind failed: {}", err);
+                return;
+            }
+        };
+        println!("Sending events to udp://{}", address);
+        // Far enough off to never fire
+        let idle = match heartbeat_ms {
+            0 => Duration::from_secs(u32::MAX as u64),
+            ms => Duration::from_millis(ms as u64),
+        };
+        loop {
+            // Events don't wait for heartbeats, so sending keys is unaffected
+            let Ok(received) = timeout(idle, events.recv()).await else {
+                let _ = socket
+                    .send_to(&packet(format, None), address.as_str())
+                    .await;
+                continue;
+            };
+            let evt = match received {
+                Ok(evt) => evt,
+                Err(RecvError::Lagged(n)) => {
+                    println!("UDP dropped {} events", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            // Nobody listening is fine, datagrams are fire and forget
+            let _ = socket
+                .send_to(&packet(format, Some(evt)), address.as_str())
+                .await;
+        }
+    });
+}
@@ -0,0 +1,194 @@
+//! vJoy output, making the ShuttlePRO a game controller: its buttons are
+//! vJoy buttons 1 up, the jog and the wheel axes.
+//!
+//! `vJoyInterface.dll` is loaded when output is opened, so machines
+//! without vJoy run as before and only enabling it reports the problem.
+
+use serde::{Deserialize, Serialize};
+use shuttle_pro_rs::ContourEvents;
+use windows::core::Result;
+#[cfg(feature = "vjoy")]
+use windows::{
+    core::{s, w, PCSTR},
+    Win32::Foundation::BOOL,
+    Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW},
+};
+
+use crate::app_error;
+use crate::config::Vjoy;
+use crate::{i18n::Msg, tr};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    None,
+    #[default]
+    X,
+    Y,
+    Z,
+    Rx,
+    Ry,
+    Rz,
+    Slider0,
+    Slider1,
+}
+
+impl Axis {
+    /// HID usage vJoy names the axis by, from `HID_USAGE_X` (0x30) on.
+    #[cfg(feature = "vjoy")]
+    fn usage(self) -> Option<u32> {
+        let index = [
+            Axis::X,
+            Axis::Y,
+            Axis::Z,
+            Axis::Rx,
+            Axis::Ry,
+            Axis::Rz,
+            Axis::Slider0,
+            Axis::Slider1,
+        ]
+        .iter()
+        .position(|a| *a == self)?;
+        Some(0x30 + index as u32)
+    }
+}
+
+/// Axis range of vJoy.
+#[cfg(feature = "vjoy")]
+const AXIS_MIN: i32 = 0x0001;
+#[cfg(feature = "vjoy")]
+const AXIS_MAX: i32 = 0x8000;
+
+// From vjoyinterface.h; all of it is __cdecl
+#[cfg(feature = "vjoy")]
+const VJD_STAT_OWN: i32 = 0;
+#[cfg(feature = "vjoy")]
+const VJD_STAT_FREE: i32 = 1;
+
+#[cfg(feature = "vjoy")]
+type StatusFn = unsafe extern "C" fn(u32) -> i32;
+#[cfg(feature = "vjoy")]
+type DeviceFn = unsafe extern "C" fn(u32) -> BOOL;
+#[cfg(feature = "vjoy")]
+type RelinquishFn = unsafe extern "C" fn(u32);
+#[cfg(feature = "vjoy")]
+type SetBtnFn = unsafe extern "C" fn(BOOL, u32, u8) -> BOOL;
+#[cfg(feature = "vjoy")]
+type SetAxisFn = unsafe extern "C" fn(i32, u32, u32) -> BOOL;
+
+#[cfg(feature = "vjoy")]
+struct Feeder {
+    device: u32,
+    set_btn: SetBtnFn,
+    set_axis: SetAxisFn,
+    relinquish: RelinquishFn,
+    buttons: bool,
+    jog: Option<u32>,
+    wheel: Option<u32>,
+    /// The wheel axis walks round its range with the ticks.
+    wheel_pos: u8,
+}
+
+#[cfg(feature = "vjoy")]
+static mut FEEDER: Option<Feeder> = None;
+
+/// Take vJoy device `config.device`, releasing any taken before.
+#[cfg(feature = "vjoy")]
+pub fn open(config: &Vjoy) -> Result<()> {
+    close();
+    let missing = || app_error(&tr(Msg::VjoyMissing, &[]));
+    let dll = unsafe { LoadLibraryW(w!("vJoyInterface.dll")) }.map_err(|_| missing())?;
+    let find = |name: PCSTR| unsafe { GetProcAddress(dll, name) }.ok_or_else(missing);
+    // Same size, only the signature differs
+    let enabled: unsafe extern "C" fn() -> BOOL =
+        unsafe { std::mem::transmute(find(s!("vJoyEnabled"))?) };
+    let status: StatusFn = unsafe { std::mem::transmute(find(s!("GetVJDStatus"))?) };
+    let acquire: DeviceFn = unsafe { std::mem::transmute(find(s!("AcquireVJD"))?) };
+    let reset: DeviceFn = unsafe { std::mem::transmute(find(s!("ResetVJD"))?) };
+    let set_btn: SetBtnFn = unsafe { std::mem::transmute(find(s!("SetBtn"))?) };
+    let set_axis: SetAxisFn = unsafe { std::mem::transmute(find(s!("SetAxis"))?) };
+    let relinquish: RelinquishFn = unsafe { std::mem::transmute(find(s!("RelinquishVJD"))?) };
+    let feeder = Feeder {
+        device: config.device,
+        set_btn,
+        set_axis,
+        relinquish,
+        buttons: config.buttons,
+        jog: config.jog_axis.usage(),
+        wheel: config.wheel_axis.usage(),
+        wheel_pos: 0,
+    };
+    if !unsafe { enabled() }.as_bool() {
+        return Err(missing());
+    }
+    let taken = match unsafe { status(config.device) } {
+        VJD_STAT_OWN => true,
+        VJD_STAT_FREE => unsafe { acquire(config.device) }.as_bool(),
+        _ => false,
+    };
+    if !taken {
+        return Err(app_error(&tr(Msg::VjoyDevice, &[&config.device])));
+    }
+    unsafe { reset(config.device) };
+    if let Some(axis) = feeder.jog {
+        unsafe { (feeder.set_axis)((AXIS_MIN + AXIS_MAX) / 2, config.device, axis) };
+    }
+    println!("Feeding vJoy device {}", config.device);
+    unsafe { FEEDER = Some(feeder) };
+    Ok(())
+}
+
+#[cfg(feature = "vjoy")]
+pub fn close() {
+    if let Some(feeder) = unsafe { std::ptr::replace(&raw mut FEEDER, None) } {
+        unsafe { (feeder.relinquish)(feeder.device) };
+    }
+}
+
+/// Mirror `evt` to the vJoy device, if one is open.
+#[cfg(feature = "vjoy")]
+pub fn feed(evt: ContourEvents) {
+    let Some(feeder) = (unsafe { crate::global(&raw mut FEEDER).as_mut() }) else {
+        return;
+    };
+    let span = AXIS_MAX - AXIS_MIN;
+    let ok = unsafe {
+        match evt {
+            ContourEvents::Jog(x) => feeder.jog.is_none_or(|axis| {
+                let jog = shuttle_pro_rs::JOG_MAX as i32;
+                let value = AXIS_MIN + (x as i32 + jog) * span / (2 * jog);
+                (feeder.set_axis)(value, feeder.device, axis).as_bool()
+            }),
+            ContourEvents::WheelLeft | ContourEvents::WheelRight => {
+                feeder.wheel_pos = if evt == ContourEvents::WheelRight {
+                    feeder.wheel_pos.wrapping_add(1)
+                } else {
+                    feeder.wheel_pos.wrapping_sub(1)
+                };
+                feeder.wheel.is_none_or(|axis| {
+                    let value = AXIS_MIN + feeder.wheel_pos as i32 * span / 255;
+                    (feeder.set_axis)(value, feeder.device, axis).as_bool()
+                })
+            }
+            ContourEvents::ButtonDown(b) | ContourEvents::ButtonUp(b) if feeder.buttons => {
+                let down = matches!(evt, ContourEvents::ButtonDown(_));
+                (feeder.set_btn)(down.into(), feeder.device, b as u8 + 1).as_bool()
+            }
+            _ => true,
+        }
+    };
+    if !ok {
+        println!("vJoy refused {:?}", evt);
+    }
+}
+
+#[cfg(not(feature = "vjoy"))]
+pub fn open(_config: &Vjoy) -> Result<()> {
+    Err(app_error(&tr(Msg::BackendDisabled, &[&"vJoy", &"vjoy"])))
+}
+
+#[cfg(not(feature = "vjoy"))]
+pub fn close() {}
+
+#[cfg(not(feature = "vjoy"))]
+pub fn feed(_evt: ContourEvents) {}